@@ -9,6 +9,12 @@ pub struct ApplicationConfig {
     pub name: String,
     /// Relative path to the executable of the application
     pub executable: PathBuf,
+    /// Seconds a newly installed version is watched for after its launch. If it exits
+    /// with an error (or crashes) meanwhile, it's marked as bad and the previous version
+    /// is restored. The first installed version has nothing to be restored to,
+    /// it's only marked as bad. Disabled if 0.
+    #[serde(rename = "health-check-window", default)]
+    pub health_check_window: u64,
 }
 
 impl Verifiable for ApplicationConfig {
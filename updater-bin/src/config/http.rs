@@ -0,0 +1,134 @@
+use super::Verifiable;
+use serde::Deserialize;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use updater::provider;
+
+/// HTTP client settings shared by every provider and download
+#[derive(Deserialize, Debug, Default)]
+pub struct HttpConfig {
+    /// Proxy url. Taken from the environment (HTTPS_PROXY, ...) if not set.
+    pub proxy: Option<String>,
+    /// Username of the proxy
+    #[serde(rename = "proxy-username")]
+    pub proxy_username: Option<String>,
+    /// Password of the proxy
+    #[serde(rename = "proxy-password")]
+    pub proxy_password: Option<String>,
+    /// PEM files of additional root certificates (relative to the updater's directory)
+    #[serde(rename = "ca-files", default)]
+    pub ca_files: Vec<PathBuf>,
+    /// Value of the User-Agent header
+    #[serde(rename = "user-agent")]
+    pub user_agent: Option<String>,
+    /// Connection timeout in seconds
+    #[serde(rename = "connect-timeout")]
+    pub connect_timeout: Option<u64>,
+    /// Read timeout in seconds
+    #[serde(rename = "read-timeout")]
+    pub read_timeout: Option<u64>,
+}
+
+impl HttpConfig {
+    /// Converts the settings into the client configuration.
+    /// * `wd` - The directory relative CA file paths are resolved against
+    pub fn client_config(&self, wd: &Path) -> provider::HttpConfig {
+        let mut config = provider::HttpConfig {
+            proxy: self.proxy.clone(),
+            proxy_username: self.proxy_username.clone(),
+            proxy_password: self.proxy_password.clone(),
+            ca_files: self.ca_files.iter().map(|path| wd.join(path)).collect(),
+            ..Default::default()
+        };
+
+        if let Some(user_agent) = self.user_agent.as_ref() {
+            config.user_agent = user_agent.clone();
+        }
+        if let Some(timeout) = self.connect_timeout {
+            config.connect_timeout = Duration::from_secs(timeout);
+        }
+        if let Some(timeout) = self.read_timeout {
+            config.read_timeout = Duration::from_secs(timeout);
+        }
+
+        config
+    }
+}
+
+impl Verifiable for HttpConfig {
+    fn verify(&self) -> Result<(), Box<dyn Error>> {
+        if self.proxy.as_ref().is_some_and(|proxy| proxy.is_empty()) {
+            return Err("Proxy url is empty".into());
+        }
+
+        if self.proxy.is_none() && self.proxy_username.is_some() {
+            return Err("Proxy username is specified without a proxy".into());
+        }
+
+        if self.proxy_username.is_none() && self.proxy_password.is_some() {
+            return Err("Proxy password is specified without a username".into());
+        }
+
+        if self
+            .user_agent
+            .as_ref()
+            .is_some_and(|agent| agent.is_empty())
+        {
+            return Err("User agent is empty".into());
+        }
+
+        if self.connect_timeout == Some(0) || self.read_timeout == Some(0) {
+            return Err("Timeouts must be at least 1 second".into());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml: &str) -> HttpConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn client_config() {
+        let cfg = parse(
+            r#"
+            proxy = "http://proxy.corp:3128"
+            proxy-username = "user"
+            proxy-password = "secret"
+            ca-files = ["certs/corp.pem"]
+            user-agent = "my-app-updater"
+            read-timeout = 60
+            "#,
+        );
+        assert!(cfg.verify().is_ok());
+
+        let config = cfg.client_config(Path::new("/opt/app"));
+        assert_eq!(config.proxy.as_deref(), Some("http://proxy.corp:3128"));
+        assert_eq!(config.proxy_username.as_deref(), Some("user"));
+        assert_eq!(config.ca_files, vec![Path::new("/opt/app/certs/corp.pem")]);
+        assert_eq!(config.user_agent, "my-app-updater");
+        assert_eq!(config.read_timeout, Duration::from_secs(60));
+        assert_eq!(
+            config.connect_timeout,
+            provider::HttpConfig::default().connect_timeout
+        );
+    }
+
+    #[test]
+    fn verify() {
+        assert!(parse("").verify().is_ok());
+        assert!(parse(r#"proxy-username = "user""#).verify().is_err());
+        assert!(
+            parse("proxy = \"http://proxy\"\nproxy-password = \"secret\"")
+                .verify()
+                .is_err()
+        );
+        assert!(parse("connect-timeout = 0").verify().is_err());
+    }
+}
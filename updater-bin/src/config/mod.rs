@@ -1,8 +1,10 @@
 mod application;
+mod http;
 mod provider;
 mod update;
 
 pub use application::ApplicationConfig;
+pub use http::HttpConfig;
 pub use provider::ProviderConfig;
 pub use update::UpdateConfig;
 
@@ -21,6 +23,9 @@ pub struct Config {
     pub application: ApplicationConfig,
     /// Update settings
     pub update: UpdateConfig,
+    /// HTTP client settings
+    #[serde(default)]
+    pub http: HttpConfig,
 }
 
 impl Config {
@@ -41,6 +46,7 @@ impl Verifiable for Config {
     fn verify(&self) -> Result<(), Box<dyn Error>> {
         self.application.verify()?;
         self.update.verify()?;
+        self.http.verify()?;
 
         Ok(())
     }
@@ -1,17 +1,68 @@
 use super::Verifiable;
 use serde::Deserialize;
 use std::error::Error;
+use std::path::PathBuf;
 
 #[derive(Deserialize, Debug)]
 pub struct ProviderConfig {
     /// GitHub provider settings
     pub github: Option<GitHubProviderSettings>,
+    /// GitLab provider settings
+    pub gitlab: Option<GitLabProviderSettings>,
+    /// Gitea/Forgejo provider settings
+    pub gitea: Option<GiteaProviderSettings>,
+    /// Static manifest provider settings
+    pub manifest: Option<ManifestProviderSettings>,
+    /// Local directory provider settings
+    pub directory: Option<DirectoryProviderSettings>,
+
+    /// Providers to try in order if the one above fails to fetch
+    #[serde(default)]
+    pub fallback: Vec<ProviderConfig>,
+    /// Mirror urls to download assets from if the original url fails.
+    /// ("{version}" and "{name}" are replaced with the version and the asset name)
+    #[serde(default)]
+    pub mirrors: Vec<String>,
+}
+
+impl ProviderConfig {
+    /// Checks if the deprecated `prereleases` key of the GitHub provider is set
+    pub fn prereleases(&self) -> bool {
+        self.github
+            .as_ref()
+            .is_some_and(|github| github.prereleases)
+            || self.fallback.iter().any(ProviderConfig::prereleases)
+    }
 }
 
 impl Verifiable for ProviderConfig {
     fn verify(&self) -> Result<(), Box<dyn Error>> {
-        if self.github.is_some() {
-            self.github.as_ref().unwrap().verify()?;
+        if let Some(github) = self.github.as_ref() {
+            github.verify()?;
+        }
+
+        if let Some(gitlab) = self.gitlab.as_ref() {
+            gitlab.verify()?;
+        }
+
+        if let Some(gitea) = self.gitea.as_ref() {
+            gitea.verify()?;
+        }
+
+        if let Some(manifest) = self.manifest.as_ref() {
+            manifest.verify()?;
+        }
+
+        if let Some(directory) = self.directory.as_ref() {
+            directory.verify()?;
+        }
+
+        for fallback in &self.fallback {
+            fallback.verify()?;
+        }
+
+        if self.mirrors.iter().any(|mirror| mirror.is_empty()) {
+            return Err("Mirror url is empty".into());
         }
 
         Ok(())
@@ -22,6 +73,42 @@ impl Verifiable for ProviderConfig {
 pub struct GitHubProviderSettings {
     /// The github repository (*user*/*repository*)
     pub repository: String,
+    /// The url of the REST API (for GitHub Enterprise Server)
+    #[serde(rename = "api-url")]
+    pub api_url: Option<String>,
+    /// Deprecated: same as `channel = "beta"` in the update settings
+    #[serde(default)]
+    pub prereleases: bool,
+    /// Access token
+    pub token: Option<String>,
+    /// Name of the environment variable containing the access token
+    #[serde(rename = "token-env")]
+    pub token_env: Option<String>,
+    /// Path to the file containing the access token
+    #[serde(rename = "token-file")]
+    pub token_file: Option<PathBuf>,
+}
+
+impl GitHubProviderSettings {
+    /// Gets the access token from the first specified source
+    pub fn token(&self) -> Result<Option<String>, Box<dyn Error>> {
+        if let Some(token) = self.token.as_ref() {
+            return Ok(Some(token.clone()));
+        }
+
+        if let Some(var) = self.token_env.as_ref() {
+            match std::env::var(var) {
+                Ok(token) => return Ok(Some(token.trim().to_string())),
+                Err(e) => return Err(format!("Failed to read {}: {}", var, e).into()),
+            }
+        }
+
+        if let Some(path) = self.token_file.as_ref() {
+            return Ok(Some(std::fs::read_to_string(path)?.trim().to_string()));
+        }
+
+        Ok(None)
+    }
 }
 
 impl Verifiable for GitHubProviderSettings {
@@ -30,12 +117,139 @@ impl Verifiable for GitHubProviderSettings {
             return Err("GitHub repository field is empty".into());
         }
 
+        if self.api_url.as_ref().is_some_and(|url| url.is_empty()) {
+            return Err("GitHub api-url field is empty".into());
+        }
+
+        let sources = [
+            self.token.is_some(),
+            self.token_env.is_some(),
+            self.token_file.is_some(),
+        ];
+        if sources.iter().filter(|&&source| source).count() > 1 {
+            return Err("Only one of token, token-env and token-file can be specified".into());
+        }
+
+        Ok(())
+    }
+}
+
+impl TryFrom<&GitHubProviderSettings> for updater::provider::GitHubProvider {
+    type Error = Box<dyn Error>;
+
+    fn try_from(settings: &GitHubProviderSettings) -> Result<Self, Self::Error> {
+        let provider = match settings.api_url.as_ref() {
+            Some(api_url) => Self::with_api_url(api_url, &settings.repository),
+            None => Self::new(&settings.repository),
+        };
+        match settings.token()? {
+            Some(token) => Ok(provider.with_token(token)),
+            None => Ok(provider),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GitLabProviderSettings {
+    /// The url of the gitlab instance
+    #[serde(default = "default_gitlab_url")]
+    pub url: String,
+    /// The gitlab project (*namespace*/*project* or project ID)
+    pub project: String,
+}
+
+impl Verifiable for GitLabProviderSettings {
+    fn verify(&self) -> Result<(), Box<dyn Error>> {
+        if self.url.is_empty() {
+            return Err("GitLab url field is empty".into());
+        }
+
+        if self.project.is_empty() {
+            return Err("GitLab project field is empty".into());
+        }
+
         Ok(())
     }
 }
 
-impl From<&GitHubProviderSettings> for updater::provider::GitHubProvider {
-    fn from(settings: &GitHubProviderSettings) -> Self {
-        Self::new(&settings.repository)
+impl From<&GitLabProviderSettings> for updater::provider::GitLabProvider {
+    fn from(settings: &GitLabProviderSettings) -> Self {
+        Self::with_url(&settings.url, &settings.project)
     }
 }
+
+#[derive(Debug, Deserialize)]
+pub struct GiteaProviderSettings {
+    /// The url of the gitea/forgejo instance
+    pub url: String,
+    /// The repository (*owner*/*repository*)
+    pub repository: String,
+}
+
+impl Verifiable for GiteaProviderSettings {
+    fn verify(&self) -> Result<(), Box<dyn Error>> {
+        if self.url.is_empty() {
+            return Err("Gitea url field is empty".into());
+        }
+
+        if self.repository.is_empty() {
+            return Err("Gitea repository field is empty".into());
+        }
+
+        Ok(())
+    }
+}
+
+impl From<&GiteaProviderSettings> for updater::provider::GiteaProvider {
+    fn from(settings: &GiteaProviderSettings) -> Self {
+        Self::new(&settings.url, &settings.repository)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ManifestProviderSettings {
+    /// The url of the manifest json file
+    pub url: String,
+}
+
+impl Verifiable for ManifestProviderSettings {
+    fn verify(&self) -> Result<(), Box<dyn Error>> {
+        if self.url.is_empty() {
+            return Err("Manifest url field is empty".into());
+        }
+
+        Ok(())
+    }
+}
+
+impl From<&ManifestProviderSettings> for updater::provider::ManifestProvider {
+    fn from(settings: &ManifestProviderSettings) -> Self {
+        Self::new(&settings.url)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DirectoryProviderSettings {
+    /// The path (or file:// url) of the directory containing the releases
+    pub path: String,
+}
+
+impl Verifiable for DirectoryProviderSettings {
+    fn verify(&self) -> Result<(), Box<dyn Error>> {
+        if self.path.is_empty() {
+            return Err("Directory path field is empty".into());
+        }
+
+        Ok(())
+    }
+}
+
+impl From<&DirectoryProviderSettings> for updater::provider::DirectoryProvider {
+    fn from(settings: &DirectoryProviderSettings) -> Self {
+        Self::new(&settings.path)
+    }
+}
+
+fn default_gitlab_url() -> String {
+    String::from("https://gitlab.com")
+}
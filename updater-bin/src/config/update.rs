@@ -1,6 +1,9 @@
 use super::{ProviderConfig, Verifiable};
 use serde::Deserialize;
 use std::error::Error;
+use updater::extract::ExtractOptions;
+use updater::signature::PublicKey;
+use updater::Channel;
 
 #[derive(Deserialize, Debug)]
 pub struct UpdateConfig {
@@ -17,26 +20,82 @@ pub struct UpdateConfig {
     #[serde(rename = "show-progress", default = "default_show_progress")]
     pub show_progress: bool,
 
+    /// The release channel to update from (stable, beta or nightly)
+    #[serde(default)]
+    pub channel: Channel,
+
+    /// Minisign public key the assets must be signed with
+    /// (base64 encoded or the content of the public key file)
+    #[serde(rename = "public-key")]
+    pub public_key: Option<String>,
+    /// Install assets without a signature. Invalid signatures are always rejected.
+    #[serde(rename = "allow-unsigned", default)]
+    pub allow_unsigned: bool,
+
+    /// Extract tar based assets (eg. .tar.gz, .tar.zst) while downloading them
+    #[serde(rename = "stream-extract", default)]
+    pub stream_extract: bool,
+
     /// The name of the asset to download
     #[serde(rename = "asset-name")]
     pub asset_name: String,
+    /// Number of leading directories to remove from the paths in the asset
+    /// (like tar's --strip-components), eg. 1 if it's wrapped in an "app-1.2.3/" directory
+    #[serde(rename = "strip-components", default)]
+    pub strip_components: usize,
+    /// Install only the content of this directory of the asset (after stripping)
+    pub subdirectory: Option<String>,
+    /// Install only the files matching any of these globs (eg. "bin/*" or "**/*.dll")
+    #[serde(default)]
+    pub include: Vec<String>,
 
     /// Provicer configuration
     pub provider: ProviderConfig,
 }
 
+impl UpdateConfig {
+    /// Gets the release channel, taking the deprecated `prereleases` key into account
+    pub fn channel(&self) -> Channel {
+        if self.provider.prereleases() {
+            log::warn!("`prereleases` is deprecated, use `channel = \"beta\"` instead");
+            return self.channel.max(Channel::Beta);
+        }
+        self.channel
+    }
+}
+
 impl Verifiable for UpdateConfig {
     fn verify(&self) -> Result<(), Box<dyn Error>> {
         if self.asset_name.is_empty() {
             return Err("Asset name is empty".into());
         }
 
+        if let Some(public_key) = self.public_key.as_ref() {
+            public_key.parse::<PublicKey>()?;
+        }
+
+        ExtractOptions::try_from(self)?;
+
         self.provider.verify()?;
 
         Ok(())
     }
 }
 
+impl TryFrom<&UpdateConfig> for ExtractOptions {
+    type Error = Box<dyn Error>;
+
+    fn try_from(config: &UpdateConfig) -> Result<Self, Self::Error> {
+        let options = Self::default()
+            .with_strip_components(config.strip_components)
+            .with_include(&config.include)?;
+        match config.subdirectory.as_ref() {
+            Some(subdirectory) => Ok(options.with_subdirectory(subdirectory)),
+            None => Ok(options),
+        }
+    }
+}
+
 fn default_before_launch() -> bool {
     false
 }
@@ -52,3 +111,35 @@ fn default_should_install() -> bool {
 fn default_show_progress() -> bool {
     false
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(toml: &str) -> UpdateConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn deprecated_prereleases() {
+        let cfg = parse(
+            r#"
+            asset-name = "app-<os>-<arch>.zip"
+            [provider.github]
+            repository = "user/app"
+            prereleases = true
+            "#,
+        );
+        assert_eq!(cfg.channel(), Channel::Beta);
+
+        let cfg = parse(
+            r#"
+            asset-name = "app-<os>-<arch>.zip"
+            channel = "nightly"
+            [provider.github]
+            repository = "user/app"
+            "#,
+        );
+        assert_eq!(cfg.channel(), Channel::Nightly);
+    }
+}
@@ -3,9 +3,53 @@ use log::{error, info};
 use semver::Version;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
 
 pub fn launch<P: AsRef<Path>>(wd: P, version: &Version, app_cfg: &ApplicationConfig) {
     info!("Launching {}", &app_cfg.name);
+    if let Err(e) = command(wd, version, app_cfg).spawn() {
+        error!("Failed to launch application: {}", e);
+    }
+}
+
+/// Launches the application and watches it for the given time.
+/// Returns false if it failed to start, exited with an error or crashed meanwhile.
+pub fn launch_supervised<P: AsRef<Path>>(
+    wd: P,
+    version: &Version,
+    app_cfg: &ApplicationConfig,
+    window: Duration,
+) -> bool {
+    info!("Launching {} under supervision", &app_cfg.name);
+    let mut child = match command(wd, version, app_cfg).spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            error!("Failed to launch application: {}", e);
+            return false;
+        }
+    };
+
+    let start = Instant::now();
+    while start.elapsed() < window {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => return true,
+            Ok(Some(status)) => {
+                error!("Application exited with {}", status);
+                return false;
+            }
+            Ok(None) => std::thread::sleep(Duration::from_millis(100)),
+            Err(e) => {
+                error!("Failed to check the application: {}", e);
+                return false;
+            }
+        }
+    }
+
+    info!("{} is running", &app_cfg.name);
+    true
+}
+
+fn command<P: AsRef<Path>>(wd: P, version: &Version, app_cfg: &ApplicationConfig) -> Command {
     let path = resolve_path(wd, version.to_string(), &app_cfg.executable);
     let args: Vec<String> = std::env::args().skip(1).collect();
 
@@ -15,10 +59,7 @@ pub fn launch<P: AsRef<Path>>(wd: P, version: &Version, app_cfg: &ApplicationCon
         .stdin(Stdio::null())
         .stdout(Stdio::null())
         .stderr(Stdio::null());
-
-    if let Err(e) = command.spawn() {
-        error!("Failed to launch application: {}", e);
-    }
+    command
 }
 
 pub fn check<P: AsRef<Path>>(wd: P, version: &Version, app_cfg: &ApplicationConfig) -> bool {
@@ -43,4 +84,49 @@ mod tests {
         let path = resolve_path("/check", "this/", "fn");
         assert_eq!(correct, path);
     }
+
+    #[test]
+    #[cfg(unix)]
+    fn supervise_launch() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let wd = std::env::temp_dir().join("updater_bin_test_supervise");
+        if wd.exists() {
+            std::fs::remove_dir_all(&wd).unwrap();
+        }
+
+        let app_cfg = ApplicationConfig {
+            name: "app".into(),
+            executable: "app".into(),
+            health_check_window: 1,
+        };
+        let window = Duration::from_millis(500);
+        for (version, script, healthy) in [
+            ("1.0.0", "exit 0", true),
+            ("1.1.0", "exit 1", false),
+            ("1.2.0", "kill -9 $$", false),
+            ("1.3.0", "sleep 2", true),
+        ] {
+            let path = wd.join(version).join("app");
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+            let version = Version::parse(version).unwrap();
+            assert_eq!(
+                launch_supervised(&wd, &version, &app_cfg, window),
+                healthy,
+                "{}",
+                script
+            );
+        }
+        assert!(!launch_supervised(
+            &wd,
+            &Version::new(2, 0, 0),
+            &app_cfg,
+            window
+        ));
+
+        std::fs::remove_dir_all(wd).unwrap();
+    }
 }
@@ -6,11 +6,12 @@ mod platform;
 mod update;
 mod version;
 
-use config::{Config, Verifiable};
+use config::{ApplicationConfig, Config, Verifiable};
 use log::{error, info, warn};
 use semver::Version;
 use std::error::Error;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use updater::Locker;
 
 fn main() {
@@ -26,6 +27,11 @@ fn start(cfg: &Config) {
     let working_dir = get_working_dir().expect("failed to get the working directory");
     info!("Working directory: {}", working_dir.display());
 
+    let http_config = cfg.http.client_config(&working_dir);
+    if let Err(e) = updater::provider::set_http_config(&http_config) {
+        error!("Failed to configure the HTTP client: {}", e);
+    }
+
     let mut version = version::read_file(version::app_file(&working_dir));
     if version.is_some() {
         // Check if the currently installed version's executable exist
@@ -37,49 +43,58 @@ fn start(cfg: &Config) {
         }
     }
 
-    // Launch application if needed
-    let mut should_launch = if !cfg.update.before_launch && version.is_some() {
-        // Launch the application specified in the config
-        launcher::launch(&working_dir, version.as_ref().unwrap(), &cfg.application);
-        false
-    } else {
-        true
-    };
-
-    // Create lockfile
+    // Create lockfile before launching, so only one updater watches a new version
     let mut locker = Locker::default();
     // Exit if the updater is already running
     if !locker.lock() {
+        if let Some(version) = version.as_ref().filter(|_| !cfg.update.before_launch) {
+            launcher::launch(&working_dir, version, &cfg.application);
+        }
         info!("Process already running!");
         std::process::exit(0);
     }
 
-    // try delete older versions
-    info!("Cleaning-up older versions");
-    if version.is_some() && clean_old_versions(&working_dir, version.as_ref().unwrap()).is_err() {
-        error!("Failed to clean old version!");
-    }
+    std::thread::scope(|scope| {
+        // Launch application if needed. It's watched in the background,
+        // so the update doesn't wait for its health check.
+        let mut should_launch = match version.clone() {
+            Some(mut version) if !cfg.update.before_launch => {
+                let wd = working_dir.as_path();
+                scope.spawn(move || launch(wd, &cfg.application, &mut version));
+                false
+            }
+            _ => true,
+        };
 
-    // Update/Install application
-    upd_app(&working_dir, cfg, &mut should_launch, &mut version);
+        // try delete older versions
+        info!("Cleaning-up older versions");
+        if version.is_some() && clean_old_versions(&working_dir, version.as_ref().unwrap()).is_err()
+        {
+            error!("Failed to clean old version!");
+        }
 
-    // Launch application if needed
-    if should_launch {
-        launcher::launch(&working_dir, version.as_ref().unwrap(), &cfg.application);
-    }
+        // Update/Install application
+        upd_app(&working_dir, cfg, &mut should_launch, &mut version);
 
-    // Update self
-    if cfg.update.update_self {
-        if let Err(err) = update::self_exe() {
-            error!("Failed to update self: {}", err);
+        // Launch application if needed
+        if should_launch {
+            launch(&working_dir, &cfg.application, version.as_mut().unwrap());
         }
-    }
+
+        // Update self
+        if cfg.update.update_self {
+            if let Err(err) = update::self_exe() {
+                error!("Failed to update self: {}", err);
+            }
+        }
+    });
 }
 
 fn upd_app(wd: &Path, cfg: &Config, should_launch: &mut bool, version: &mut Option<Version>) {
     if version.is_some() || cfg.update.should_install {
         let ver = version.clone().unwrap_or_else(|| Version::new(0, 0, 0));
-        *version = match update::application(wd, cfg, ver) {
+        let blocked = version::read_list(version::bad_file(wd));
+        *version = match update::application(wd, cfg, ver, blocked) {
             Ok(v) => {
                 if version::write_file(version::app_file(wd), &v).is_err() {
                     error!("Failed to update version file");
@@ -97,6 +112,47 @@ fn upd_app(wd: &Path, cfg: &Config, should_launch: &mut bool, version: &mut Opti
     }
 }
 
+/// Launches the application. A version which didn't start successfully yet is watched
+/// and rolled back to the last known-good version if it fails.
+fn launch(wd: &Path, app_cfg: &ApplicationConfig, version: &mut Version) {
+    let window = app_cfg.health_check_window;
+    let known_good = version::read_file(version::known_good_file(wd));
+    if window == 0 || known_good.as_ref() == Some(version) {
+        launcher::launch(wd, version, app_cfg);
+        return;
+    }
+
+    if known_good.is_none() {
+        warn!(
+            "No known-good version yet, v{} can't be rolled back if it fails to start",
+            version
+        );
+    }
+    if launcher::launch_supervised(wd, version, app_cfg, Duration::from_secs(window)) {
+        if version::write_file(version::known_good_file(wd), version).is_err() {
+            error!("Failed to update known-good version file");
+        }
+        return;
+    }
+
+    error!("v{} failed to start, marking it as bad", version);
+    if version::add_to_list(version::bad_file(wd), version).is_err() {
+        error!("Failed to update bad versions file");
+    }
+
+    match known_good {
+        Some(good) if launcher::check(wd, &good, app_cfg) => {
+            warn!("Rolling back to v{}", good);
+            if version::write_file(version::app_file(wd), &good).is_err() {
+                error!("Failed to update version file");
+            }
+            *version = good;
+            launcher::launch(wd, version, app_cfg);
+        }
+        _ => error!("No known-good version to roll back to!"),
+    }
+}
+
 /// Loads the configuration from file. Exits the program on error.
 fn load_config() -> Config {
     let cfg = Config::load().unwrap_or_else(|e| {
@@ -120,6 +176,9 @@ fn get_working_dir() -> Result<PathBuf, Box<dyn Error>> {
 fn clean_old_versions(wd: &Path, version: &Version) -> Result<(), Box<dyn Error>> {
     //let version_name = std::ffi::OsString::from(version.to_string());
     let dirs = std::fs::read_dir(wd)?;
+    // Kept to roll back to
+    let known_good = version::read_file(version::known_good_file(wd));
+    let bad = version::read_list(version::bad_file(wd));
 
     // Iterate over directories
     for dir in dirs.flatten() {
@@ -128,7 +187,8 @@ fn clean_old_versions(wd: &Path, version: &Version) -> Result<(), Box<dyn Error>
             // Try to convert the name to semver
             if let Ok(dir_version) = Version::parse(dir_name) {
                 // Compare the dir_version to the current version
-                if dir_version < *version {
+                let old = dir_version < *version && Some(&dir_version) != known_good.as_ref();
+                if old || (dir_version != *version && bad.contains(&dir_version)) {
                     // If its older, delete the directory
                     let dir_path = dir.path();
                     if std::fs::remove_dir_all(dir_path).is_err() {
@@ -178,4 +238,43 @@ mod tests {
         let dir = get_working_dir().expect("get_working_dir() failed!");
         assert!(dir.is_dir());
     }
+
+    #[test]
+    #[cfg(unix)]
+    fn roll_back_failed_version() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let wd = std::env::temp_dir().join("updater_bin_test_rollback");
+        if wd.exists() {
+            std::fs::remove_dir_all(&wd).unwrap();
+        }
+        for (version, script) in [("1.0.0", "exit 0"), ("1.1.0", "exit 1")] {
+            let path = wd.join(version).join("app");
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        version::write_file(version::known_good_file(&wd), &Version::new(1, 0, 0)).unwrap();
+        version::write_file(version::app_file(&wd), &Version::new(1, 1, 0)).unwrap();
+
+        let app_cfg = ApplicationConfig {
+            name: "app".into(),
+            executable: "app".into(),
+            health_check_window: 1,
+        };
+        let mut version = Version::new(1, 1, 0);
+        launch(&wd, &app_cfg, &mut version);
+
+        assert_eq!(version, Version::new(1, 0, 0));
+        assert_eq!(
+            version::read_file(version::app_file(&wd)),
+            Some(Version::new(1, 0, 0))
+        );
+        assert_eq!(
+            version::read_list(version::bad_file(&wd)),
+            [Version::new(1, 1, 0)]
+        );
+
+        std::fs::remove_dir_all(wd).unwrap();
+    }
 }
@@ -2,22 +2,35 @@ use crate::config::{Config, ProviderConfig};
 use semver::Version;
 use std::error::Error;
 use std::path::Path;
+use updater::extract::ExtractOptions;
 use updater::procedures::application::{create, UpdateData};
-use updater::provider::{GitHubProvider, Provider};
+use updater::provider::{
+    DirectoryProvider, FallbackProvider, GitHubProvider, GitLabProvider, GiteaProvider,
+    ManifestProvider, Provider, ResponseCache,
+};
 
 pub fn application<P: AsRef<Path>>(
     wd: P,
     cfg: &Config,
     version: Version,
+    blocked: Vec<Version>,
 ) -> Result<Version, Box<dyn Error>> {
-    let provider = get_provider(&cfg.update.provider)?;
-    let data = UpdateData::new(
+    let mut provider = get_provider(&cfg.update.provider)?;
+    provider.set_channel(cfg.update.channel());
+    provider.set_cache(ResponseCache::new(wd.as_ref().join("cache")));
+    let mut data = UpdateData::new(
         provider,
         cfg.application.name.clone(),
         super::convert_asset_name(&cfg.update.asset_name),
         wd.as_ref().to_path_buf(),
         version,
     );
+    data.download_dir = Some(wd.as_ref().join("cache").join("downloads"));
+    data.public_key = super::public_key(cfg.update.public_key.as_deref())?;
+    data.allow_unsigned = cfg.update.allow_unsigned;
+    data.stream_extract = cfg.update.stream_extract;
+    data.extract = ExtractOptions::try_from(&cfg.update)?;
+    data.blocked_versions = blocked;
 
     let mut procedure = create(data);
     procedure.execute()?;
@@ -26,12 +39,104 @@ pub fn application<P: AsRef<Path>>(
         return Err("Update cancelled!".into());
     }
 
-    Ok(procedure.data().latest.as_ref().unwrap().clone())
+    Ok(installed_version(procedure.data()))
+}
+
+/// Gets the version installed after the update. The latest version of the provider
+/// is only installed if it's newer (eg. not after switching to an older channel).
+fn installed_version(data: &UpdateData) -> Version {
+    match data.latest.as_ref() {
+        Some(latest) if latest > &data.version => latest.clone(),
+        _ => data.version.clone(),
+    }
 }
 
 fn get_provider(p_cfg: &ProviderConfig) -> Result<Box<dyn Provider>, Box<dyn Error>> {
+    let mut providers = Vec::new();
+    let mut mirrors = Vec::new();
+    collect_providers(p_cfg, &mut providers, &mut mirrors)?;
+
+    match providers.len() {
+        0 => Err("No provider was specified!".into()),
+        1 if mirrors.is_empty() => Ok(providers.remove(0)),
+        _ => Ok(Box::new(
+            FallbackProvider::new(providers).with_mirrors(mirrors),
+        )),
+    }
+}
+
+/// Collects the providers and mirrors from the config and its fallbacks in order
+fn collect_providers(
+    p_cfg: &ProviderConfig,
+    providers: &mut Vec<Box<dyn Provider>>,
+    mirrors: &mut Vec<String>,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(provider) = single_provider(p_cfg)? {
+        providers.push(provider);
+    }
+    mirrors.extend(p_cfg.mirrors.iter().cloned());
+
+    for fallback in &p_cfg.fallback {
+        collect_providers(fallback, providers, mirrors)?;
+    }
+
+    Ok(())
+}
+
+/// Creates the provider specified by the config (without its fallbacks)
+fn single_provider(p_cfg: &ProviderConfig) -> Result<Option<Box<dyn Provider>>, Box<dyn Error>> {
     if let Some(gh_cfg) = p_cfg.github.as_ref() {
-        return Ok(Box::new(GitHubProvider::from(gh_cfg)));
+        return Ok(Some(Box::new(GitHubProvider::try_from(gh_cfg)?)));
+    }
+    if let Some(gl_cfg) = p_cfg.gitlab.as_ref() {
+        return Ok(Some(Box::new(GitLabProvider::from(gl_cfg))));
+    }
+    if let Some(gt_cfg) = p_cfg.gitea.as_ref() {
+        return Ok(Some(Box::new(GiteaProvider::from(gt_cfg))));
+    }
+    if let Some(mf_cfg) = p_cfg.manifest.as_ref() {
+        return Ok(Some(Box::new(ManifestProvider::from(mf_cfg))));
+    }
+    if let Some(dir_cfg) = p_cfg.directory.as_ref() {
+        return Ok(Some(Box::new(DirectoryProvider::from(dir_cfg))));
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use updater::Channel;
+
+    #[test]
+    fn downgrade_after_channel_change() {
+        let dir = std::env::temp_dir().join("updater_bin_test_downgrade");
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("app-1.0.0.zip"), "1.0.0").unwrap();
+        std::fs::write(dir.join("app-1.1.0-beta.1.zip"), "1.1.0-beta.1").unwrap();
+
+        // Switched back to stable from the installed beta
+        let mut provider = DirectoryProvider::new(dir.to_str().unwrap());
+        provider.set_channel(Channel::Stable);
+        provider.fetch().unwrap();
+        let installed = Version::parse("1.1.0-beta.1").unwrap();
+        let mut data = UpdateData::new(
+            Box::new(provider),
+            "app".into(),
+            "app.zip".into(),
+            dir.clone(),
+            installed.clone(),
+        );
+        data.latest = Some(data.provider.latest().unwrap());
+        assert_eq!(data.latest, Some(Version::new(1, 0, 0)));
+        assert_eq!(installed_version(&data), installed);
+
+        data.latest = Some(Version::new(1, 1, 0));
+        assert_eq!(installed_version(&data), Version::new(1, 1, 0));
+
+        std::fs::remove_dir_all(dir).unwrap();
     }
-    Err("No provider was specified!".into())
 }
@@ -4,6 +4,17 @@ mod selfexe;
 pub use application::application;
 pub use selfexe::self_exe;
 
+use std::error::Error;
+use updater::signature::PublicKey;
+
+/// Public key compiled into the updater (`UPDATER_PUBLIC_KEY` environment variable at build time)
+const PUBLIC_KEY: Option<&str> = option_env!("UPDATER_PUBLIC_KEY");
+
+/// Gets the configured public key or the one compiled into the updater
+pub fn public_key(configured: Option<&str>) -> Result<Option<PublicKey>, Box<dyn Error>> {
+    configured.or(PUBLIC_KEY).map(str::parse).transpose()
+}
+
 pub fn convert_asset_name(name: &str) -> String {
     use crate::platform::{ARCH, OS};
     let new_name = name.replace("<os>", OS);
@@ -8,7 +8,10 @@ pub fn self_exe() -> Result<(), Box<dyn Error>> {
     let provider = Box::new(GitHubProvider::new("AmionSky/updater"));
     let asset_name = super::convert_asset_name("updater-<os>-<arch>.exe");
 
-    let data = UpdateData::new(provider, Version::parse(PKG_VERSION)?, asset_name);
+    let mut data = UpdateData::new(provider, Version::parse(PKG_VERSION)?, asset_name);
+    if let Some(public_key) = super::public_key(None)? {
+        data = data.with_public_key(public_key);
+    }
 
     let mut procedure = create(data);
     procedure.execute()?;
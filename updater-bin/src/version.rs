@@ -8,6 +8,16 @@ pub fn app_file<P: AsRef<Path>>(wd: P) -> PathBuf {
     wd.as_ref().join("version.txt")
 }
 
+/// File of the last version which started successfully
+pub fn known_good_file<P: AsRef<Path>>(wd: P) -> PathBuf {
+    wd.as_ref().join("known-good.txt")
+}
+
+/// File of the versions which failed to start, one per line
+pub fn bad_file<P: AsRef<Path>>(wd: P) -> PathBuf {
+    wd.as_ref().join("bad-versions.txt")
+}
+
 pub fn read_file<P: AsRef<Path>>(version_file: P) -> Option<Version> {
     if version_file.as_ref().exists() {
         let text = std::fs::read_to_string(version_file).ok()?;
@@ -21,3 +31,23 @@ pub fn write_file<P: AsRef<Path>>(file: P, version: &Version) -> Result<(), Box<
     std::fs::write(file, version.to_string())?;
     Ok(())
 }
+
+/// Reads the list of versions, skipping the invalid lines
+pub fn read_list<P: AsRef<Path>>(file: P) -> Vec<Version> {
+    let text = std::fs::read_to_string(file).unwrap_or_default();
+    text.lines()
+        .filter_map(|line| Version::parse(line.trim()).ok())
+        .collect()
+}
+
+/// Adds the version to the list if it's not in it yet
+pub fn add_to_list<P: AsRef<Path>>(file: P, version: &Version) -> Result<(), Box<dyn Error>> {
+    let mut list = read_list(&file);
+    if !list.contains(version) {
+        list.push(version.clone());
+    }
+
+    let text: Vec<String> = list.iter().map(Version::to_string).collect();
+    std::fs::write(file, text.join("\n") + "\n")?;
+    Ok(())
+}
@@ -0,0 +1,316 @@
+mod manifest;
+mod minisign;
+mod publish;
+
+use lexopt::prelude::*;
+use minisign::SecretKey;
+use publish::{Artifact, Publish};
+use semver::Version;
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use updater::sync::FileList;
+use updater::{delta, Channel};
+
+/// Environment variable with the content of the secret key, used if `--secret-key` is missing
+const SECRET_KEY_ENV: &str = "UPDATER_SECRET_KEY";
+
+const USAGE: &str = "Usage: updater-publish <command> [options]
+
+Commands:
+  keygen [--secret-key FILE] [--public-key FILE] [--force]
+      Generates a new keypair (default: updater.key and updater.pub)
+  pubkey [--secret-key FILE]
+      Prints the public key to use in the updater's config
+  sign [--secret-key FILE] FILES...
+      Writes a FILE.minisig signature next to every file
+  checksums [--output FILE] FILES...
+      Prints (or writes) the SHA-256 checksums of the files
+  files --dir DIR --output FILE
+      Lists the files of the extracted asset with their hashes. Publish it with
+      the release as \"<asset name>.files.json\" to install only the changed files
+  patch --old DIR --new DIR --output FILE
+      Creates a patch from the old to the new install directory. Publish it with
+      the new version as \"<old version>-<asset name>.patch\" (eg. 1.1.0-app-win-x64.zip.patch)
+  publish --version VERSION --out DIR [options] ARTIFACTS...
+      Copies the artifacts into DIR/VERSION with their signatures and checksums
+      and adds the release to DIR/manifest.json
+      --channel CHANNEL     stable, beta or nightly (derived from the version if missing)
+      --notes TEXT          release notes
+      --notes-file FILE     release notes read from a file
+      --base-url URL        url of DIR, for absolute asset urls in the manifest
+      --secret-key FILE     key to sign the artifacts with
+
+The secret key is read from the UPDATER_SECRET_KEY environment variable if --secret-key is missing.";
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let mut parser = lexopt::Parser::from_env();
+    let command = match parser.next()? {
+        Some(Value(command)) => command.string()?,
+        Some(Short('h')) | Some(Long("help")) | None => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        Some(arg) => return Err(arg.unexpected().into()),
+    };
+
+    match command.as_str() {
+        "keygen" => keygen(&mut parser),
+        "pubkey" => pubkey(&mut parser),
+        "sign" => sign(&mut parser),
+        "checksums" => checksums(&mut parser),
+        "files" => files(&mut parser),
+        "patch" => patch(&mut parser),
+        "publish" => publish(&mut parser),
+        _ => Err(format!("Unknown command: {}\n\n{}", command, USAGE).into()),
+    }
+}
+
+fn keygen(parser: &mut lexopt::Parser) -> Result<(), Box<dyn Error>> {
+    let mut secret_path = PathBuf::from("updater.key");
+    let mut public_path = PathBuf::from("updater.pub");
+    let mut force = false;
+
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Long("secret-key") => secret_path = parser.value()?.into(),
+            Long("public-key") => public_path = parser.value()?.into(),
+            Long("force") => force = true,
+            _ => return Err(arg.unexpected().into()),
+        }
+    }
+
+    if !force {
+        for path in [&secret_path, &public_path] {
+            if path.exists() {
+                return Err(format!("{} already exists (use --force)", path.display()).into());
+            }
+        }
+    }
+
+    let key = SecretKey::generate();
+    write_secret(&secret_path, &key.encode())?;
+    std::fs::write(&public_path, key.public_key().encode())?;
+
+    println!("Secret key: {}", secret_path.display());
+    println!("Public key: {}", public_path.display());
+    println!("{}", key.public_key().to_base64());
+    Ok(())
+}
+
+fn pubkey(parser: &mut lexopt::Parser) -> Result<(), Box<dyn Error>> {
+    let mut secret_path = None;
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Long("secret-key") => secret_path = Some(parser.value()?.into()),
+            _ => return Err(arg.unexpected().into()),
+        }
+    }
+
+    let key = secret_key(secret_path)?.ok_or("No secret key specified")?;
+    println!("{}", key.public_key().to_base64());
+    Ok(())
+}
+
+fn sign(parser: &mut lexopt::Parser) -> Result<(), Box<dyn Error>> {
+    let mut secret_path = None;
+    let mut files: Vec<PathBuf> = Vec::new();
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Long("secret-key") => secret_path = Some(parser.value()?.into()),
+            Value(file) => files.push(file.into()),
+            _ => return Err(arg.unexpected().into()),
+        }
+    }
+
+    let key = secret_key(secret_path)?.ok_or("No secret key specified")?;
+    for file in files {
+        let artifact = Artifact::read(&file)?;
+        let dir = file.parent().unwrap_or(Path::new("."));
+        let path = artifact.signature_path(dir);
+        std::fs::write(&path, artifact.sign(&key))?;
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+fn checksums(parser: &mut lexopt::Parser) -> Result<(), Box<dyn Error>> {
+    let mut output: Option<PathBuf> = None;
+    let mut files: Vec<PathBuf> = Vec::new();
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Short('o') | Long("output") => output = Some(parser.value()?.into()),
+            Value(file) => files.push(file.into()),
+            _ => return Err(arg.unexpected().into()),
+        }
+    }
+
+    let artifacts = files
+        .iter()
+        .map(|file| Artifact::read(file))
+        .collect::<Result<Vec<_>, _>>()?;
+    let content = publish::checksums(&artifacts);
+    match output {
+        Some(path) => std::fs::write(path, content)?,
+        None => print!("{}", content),
+    }
+    Ok(())
+}
+
+fn files(parser: &mut lexopt::Parser) -> Result<(), Box<dyn Error>> {
+    let mut dir: Option<PathBuf> = None;
+    let mut output: Option<PathBuf> = None;
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Long("dir") => dir = Some(parser.value()?.into()),
+            Short('o') | Long("output") => output = Some(parser.value()?.into()),
+            _ => return Err(arg.unexpected().into()),
+        }
+    }
+
+    let dir = dir.ok_or("Missing --dir")?;
+    let output = output.ok_or("Missing --output")?;
+
+    let list = FileList::create(&dir)?;
+    std::fs::write(&output, json::to_string_pretty(&list)? + "\n")?;
+
+    println!("{} ({} files)", output.display(), list.files.len());
+    Ok(())
+}
+
+fn patch(parser: &mut lexopt::Parser) -> Result<(), Box<dyn Error>> {
+    let mut old: Option<PathBuf> = None;
+    let mut new: Option<PathBuf> = None;
+    let mut output: Option<PathBuf> = None;
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Long("old") => old = Some(parser.value()?.into()),
+            Long("new") => new = Some(parser.value()?.into()),
+            Short('o') | Long("output") => output = Some(parser.value()?.into()),
+            _ => return Err(arg.unexpected().into()),
+        }
+    }
+
+    let old = old.ok_or("Missing --old")?;
+    let new = new.ok_or("Missing --new")?;
+    let output = output.ok_or("Missing --output")?;
+
+    let file = std::io::BufWriter::new(std::fs::File::create(&output)?);
+    delta::create(&old, &new, file)?.flush()?;
+
+    println!("{}", output.display());
+    Ok(())
+}
+
+fn publish(parser: &mut lexopt::Parser) -> Result<(), Box<dyn Error>> {
+    let mut version: Option<Version> = None;
+    let mut out: Option<PathBuf> = None;
+    let mut channel = None;
+    let mut notes = None;
+    let mut base_url = None;
+    let mut secret_path = None;
+    let mut artifacts: Vec<PathBuf> = Vec::new();
+
+    while let Some(arg) = parser.next()? {
+        match arg {
+            Long("version") => version = Some(parser.value()?.parse()?),
+            Long("out") => out = Some(parser.value()?.into()),
+            Long("channel") => {
+                let value: String = parser.value()?.string()?;
+                // Validate it the way the updater reads it
+                channel = Some(value.parse::<Channel>()?.to_string());
+            }
+            Long("notes") => notes = Some(parser.value()?.string()?),
+            Long("notes-file") => {
+                let path: PathBuf = parser.value()?.into();
+                notes = Some(std::fs::read_to_string(path)?.trim().to_string());
+            }
+            Long("base-url") => base_url = Some(parser.value()?.string()?),
+            Long("secret-key") => secret_path = Some(parser.value()?.into()),
+            Value(artifact) => artifacts.push(artifact.into()),
+            _ => return Err(arg.unexpected().into()),
+        }
+    }
+
+    let version = version.ok_or("Missing --version")?;
+    let out = out.ok_or("Missing --out")?;
+    if artifacts.is_empty() {
+        return Err("No artifacts specified".into());
+    }
+
+    let key = secret_key(secret_path)?;
+    if key.is_none() {
+        eprintln!("Warning: no secret key specified, the artifacts won't be signed");
+    }
+
+    Publish {
+        version,
+        channel,
+        notes,
+        base_url,
+        out,
+    }
+    .run(&artifacts, key.as_ref())
+}
+
+/// Reads the secret key from the file or from the environment variable
+fn secret_key(path: Option<PathBuf>) -> Result<Option<SecretKey>, Box<dyn Error>> {
+    let content = match path {
+        Some(path) => std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?,
+        None => match std::env::var(SECRET_KEY_ENV) {
+            Ok(content) if !content.trim().is_empty() => content,
+            _ => return Ok(None),
+        },
+    };
+    Ok(Some(SecretKey::decode(&content)?))
+}
+
+/// Writes the secret key readable only by the owner
+fn write_secret(path: &Path, content: &str) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    // The mode only applies to new files, an overwritten one keeps its permissions
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    }
+
+    file.write_all(content.as_bytes())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overwrite_secret() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join("updater_publish_test_secret.key");
+        std::fs::write(&path, "old").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_secret(&path, "secret").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "secret");
+        let mode = path.metadata().unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
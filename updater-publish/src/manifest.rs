@@ -0,0 +1,95 @@
+//! The release manifest read by `updater::provider::ManifestProvider`.
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::io::ErrorKind;
+use std::path::Path;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub releases: Vec<Release>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Release {
+    pub version: Version,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub size: u64,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+impl Manifest {
+    /// Loads the manifest from disk. Returns an empty manifest if the file doesn't exist.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        match std::fs::read_to_string(path) {
+            Ok(content) => Ok(json::from_str(&content)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        std::fs::write(path, json::to_string_pretty(self)? + "\n")?;
+        Ok(())
+    }
+
+    /// Adds the release, replacing the one with the same version. Newest release first.
+    pub fn add(&mut self, release: Release) {
+        self.releases.retain(|r| r.version != release.version);
+        self.releases.push(release);
+        self.releases.sort_by(|a, b| b.version.cmp(&a.version));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn release(version: &str) -> Release {
+        Release {
+            version: Version::parse(version).unwrap(),
+            channel: None,
+            notes: None,
+            assets: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn add_releases() {
+        let mut manifest = Manifest::default();
+        manifest.add(release("1.0.0"));
+        manifest.add(release("1.2.0"));
+        manifest.add(release("1.1.0"));
+
+        let mut replacement = release("1.2.0");
+        replacement.notes = Some("Fixed".into());
+        manifest.add(replacement);
+
+        let versions: Vec<String> = manifest
+            .releases
+            .iter()
+            .map(|r| r.version.to_string())
+            .collect();
+        assert_eq!(versions, ["1.2.0", "1.1.0", "1.0.0"]);
+        assert_eq!(manifest.releases[0].notes.as_deref(), Some("Fixed"));
+    }
+
+    #[test]
+    fn load_missing() {
+        let manifest = Manifest::load(Path::new("/this/manifest/does/not/exist.json")).unwrap();
+        assert!(manifest.releases.is_empty());
+    }
+}
@@ -0,0 +1,219 @@
+//! Minisign compatible keys and signatures.
+//!
+//! Secret keys are stored unencrypted (like `minisign -G -W`), so they can be kept in a CI secret.
+//! Signatures are always pre-hashed with BLAKE2b-512, the default of minisign.
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use blake2::digest::consts::U32;
+use blake2::{Blake2b, Digest};
+use ed25519_dalek::{Signer, SigningKey};
+use rand_core::{OsRng, RngCore};
+use std::error::Error;
+
+/// Signature algorithm of the keys
+const SIG_ALG: &[u8; 2] = b"Ed";
+/// Signature algorithm of the pre-hashed signatures
+const SIG_ALG_HASHED: &[u8; 2] = b"ED";
+/// Key derivation algorithm of unencrypted secret keys
+const KDF_NONE: &[u8; 2] = &[0, 0];
+/// Checksum algorithm of the secret keys
+const CHK_ALG: &[u8; 2] = b"B2";
+
+/// Length of the decoded secret key
+const SECRET_KEY_LEN: usize = 158;
+
+type Blake2b256 = Blake2b<U32>;
+
+/// Secret key to sign the assets with
+pub struct SecretKey {
+    key_id: [u8; 8],
+    signing_key: SigningKey,
+}
+
+impl SecretKey {
+    /// Generates a new random key
+    pub fn generate() -> Self {
+        let mut key_id = [0; 8];
+        OsRng.fill_bytes(&mut key_id);
+
+        Self {
+            key_id,
+            signing_key: SigningKey::generate(&mut OsRng),
+        }
+    }
+
+    /// Decodes the content of a secret key file
+    pub fn decode(content: &str) -> Result<Self, Box<dyn Error>> {
+        let bytes = BASE64.decode(data_line(content).ok_or("Empty secret key")?)?;
+        if bytes.len() != SECRET_KEY_LEN || &bytes[0..2] != SIG_ALG || &bytes[4..6] != CHK_ALG {
+            return Err("Invalid secret key".into());
+        }
+        if &bytes[2..4] != KDF_NONE {
+            return Err("Encrypted secret keys are not supported (use an unencrypted key)".into());
+        }
+
+        let key_id: [u8; 8] = bytes[54..62].try_into()?;
+        let keypair: [u8; 64] = bytes[62..126].try_into()?;
+        if bytes[126..158] != checksum(&key_id, &keypair) {
+            return Err("Secret key checksum mismatch".into());
+        }
+
+        Ok(Self {
+            key_id,
+            signing_key: SigningKey::from_keypair_bytes(&keypair)?,
+        })
+    }
+
+    /// Encodes the key into the content of a secret key file
+    pub fn encode(&self) -> String {
+        let keypair = self.signing_key.to_keypair_bytes();
+
+        let mut bytes = Vec::with_capacity(SECRET_KEY_LEN);
+        bytes.extend_from_slice(SIG_ALG);
+        bytes.extend_from_slice(KDF_NONE);
+        bytes.extend_from_slice(CHK_ALG);
+        // No salt, opslimit and memlimit without key derivation
+        bytes.extend_from_slice(&[0; 48]);
+        bytes.extend_from_slice(&self.key_id);
+        bytes.extend_from_slice(&keypair);
+        bytes.extend_from_slice(&checksum(&self.key_id, &keypair));
+
+        format!(
+            "untrusted comment: minisign secret key {}\n{}\n",
+            key_id_hex(&self.key_id),
+            BASE64.encode(bytes)
+        )
+    }
+
+    /// Gets the public key of this key
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey {
+            key_id: self.key_id,
+            key: self.signing_key.verifying_key().to_bytes(),
+        }
+    }
+
+    /// Signs the BLAKE2b-512 hash of the content. Returns the content of the signature file.
+    pub fn sign_hash(&self, hash: &[u8], trusted_comment: &str) -> String {
+        let signature = self.signing_key.sign(hash).to_bytes();
+
+        let mut signature_bytes = Vec::with_capacity(74);
+        signature_bytes.extend_from_slice(SIG_ALG_HASHED);
+        signature_bytes.extend_from_slice(&self.key_id);
+        signature_bytes.extend_from_slice(&signature);
+
+        // The global signature covers the trusted comment too
+        let mut global = signature.to_vec();
+        global.extend_from_slice(trusted_comment.as_bytes());
+        let global = self.signing_key.sign(&global).to_bytes();
+
+        format!(
+            "untrusted comment: signature from minisign secret key\n{}\ntrusted comment: {}\n{}\n",
+            BASE64.encode(signature_bytes),
+            trusted_comment,
+            BASE64.encode(global)
+        )
+    }
+}
+
+/// Public key to verify the signatures with
+pub struct PublicKey {
+    key_id: [u8; 8],
+    key: [u8; 32],
+}
+
+impl PublicKey {
+    /// Gets the base64 encoded key (as used in the updater's config)
+    pub fn to_base64(&self) -> String {
+        let mut bytes = Vec::with_capacity(42);
+        bytes.extend_from_slice(SIG_ALG);
+        bytes.extend_from_slice(&self.key_id);
+        bytes.extend_from_slice(&self.key);
+        BASE64.encode(bytes)
+    }
+
+    /// Encodes the key into the content of a public key file
+    pub fn encode(&self) -> String {
+        format!(
+            "untrusted comment: minisign public key {}\n{}\n",
+            key_id_hex(&self.key_id),
+            self.to_base64()
+        )
+    }
+}
+
+/// Gets the first line which isn't a comment
+fn data_line(content: &str) -> Option<&str> {
+    content
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with("untrusted comment:"))
+}
+
+fn checksum(key_id: &[u8; 8], keypair: &[u8; 64]) -> [u8; 32] {
+    let mut hasher = Blake2b256::new();
+    hasher.update(SIG_ALG);
+    hasher.update(key_id);
+    hasher.update(keypair);
+    hasher.finalize().into()
+}
+
+/// Key id the way minisign displays it
+fn key_id_hex(key_id: &[u8; 8]) -> String {
+    format!("{:016X}", u64::from_le_bytes(*key_id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blake2::Blake2b512;
+    use updater::signature::PublicKey as VerifyingKey;
+
+    #[test]
+    fn encode_decode() {
+        let key = SecretKey::generate();
+        let decoded = SecretKey::decode(&key.encode()).unwrap();
+        assert_eq!(decoded.key_id, key.key_id);
+        assert_eq!(
+            decoded.public_key().to_base64(),
+            key.public_key().to_base64()
+        );
+
+        // Corrupted key
+        let mut bytes = BASE64.decode(data_line(&key.encode()).unwrap()).unwrap();
+        bytes[100] ^= 1;
+        assert!(SecretKey::decode(&BASE64.encode(bytes)).is_err());
+
+        assert!(SecretKey::decode("untrusted comment: nothing\n").is_err());
+    }
+
+    #[test]
+    fn public_key_readable() {
+        let key = SecretKey::generate().public_key();
+        assert!(key.to_base64().parse::<VerifyingKey>().is_ok());
+        assert!(key.encode().parse::<VerifyingKey>().is_ok());
+    }
+
+    #[test]
+    fn signature_verifies() {
+        let key = SecretKey::generate();
+        let trusted_comment = "timestamp:0\tfile:app.zip\thashed";
+        let hash = Blake2b512::digest(b"release content");
+        let signature = key.sign_hash(&hash, trusted_comment);
+
+        let public_key =
+            minisign_verify::PublicKey::from_base64(&key.public_key().to_base64()).unwrap();
+        let signature = minisign_verify::Signature::decode(&signature).unwrap();
+        assert_eq!(signature.trusted_comment(), trusted_comment);
+        assert!(public_key
+            .verify(b"release content", &signature, false)
+            .is_ok());
+        assert!(public_key.verify(b"tampered", &signature, false).is_err());
+
+        // Signed by another key
+        let other = SecretKey::generate().public_key().to_base64();
+        let other = minisign_verify::PublicKey::from_base64(&other).unwrap();
+        assert!(other.verify(b"release content", &signature, false).is_err());
+    }
+}
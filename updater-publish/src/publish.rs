@@ -0,0 +1,264 @@
+//! Hashing, signing and publishing of the build artifacts.
+
+use crate::manifest::{self, Manifest, Release};
+use crate::minisign::SecretKey;
+use blake2::Blake2b512;
+use semver::Version;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Name of the checksum file written next to the assets
+pub const CHECKSUMS: &str = "SHA256SUMS";
+
+/// Build artifact with its size and hashes
+pub struct Artifact {
+    pub name: String,
+    pub size: u64,
+    pub sha256: String,
+    /// BLAKE2b-512 hash for the signature
+    blake2b: Vec<u8>,
+}
+
+impl Artifact {
+    /// Reads the file and computes its hashes in one pass
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => name.to_string(),
+            None => return Err(format!("Invalid file name: {}", path.display()).into()),
+        };
+
+        let mut file = File::open(path)?;
+        let mut sha256 = Sha256::new();
+        let mut blake2b = Blake2b512::new();
+        let mut size = 0;
+
+        let mut buf = [0; 65536];
+        loop {
+            let len = match file.read(&mut buf) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            sha256.update(&buf[..len]);
+            blake2b.update(&buf[..len]);
+            size += len as u64;
+        }
+
+        Ok(Self {
+            name,
+            size,
+            sha256: sha256
+                .finalize()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect(),
+            blake2b: blake2b.finalize().to_vec(),
+        })
+    }
+
+    /// Signs the artifact. Returns the content of its `.minisig` file.
+    pub fn sign(&self, key: &SecretKey) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+        let trusted_comment = format!("timestamp:{}\tfile:{}\thashed", timestamp, self.name);
+        key.sign_hash(&self.blake2b, &trusted_comment)
+    }
+
+    /// Path of the signature file next to the artifact
+    pub fn signature_path(&self, dir: &Path) -> PathBuf {
+        dir.join(format!("{}.minisig", self.name))
+    }
+}
+
+/// Content of a checksum file (GNU coreutils format)
+pub fn checksums(artifacts: &[Artifact]) -> String {
+    artifacts
+        .iter()
+        .map(|a| format!("{}  {}\n", a.sha256, a.name))
+        .collect()
+}
+
+/// Settings of a published release
+pub struct Publish {
+    pub version: Version,
+    pub channel: Option<String>,
+    pub notes: Option<String>,
+    /// Url of the published directory. Asset urls are relative to the manifest without it.
+    pub base_url: Option<String>,
+    /// Directory to publish into
+    pub out: PathBuf,
+}
+
+impl Publish {
+    /// Publishes the artifacts into the output directory:
+    /// * `<out>/<version>/<artifact>` and its `.minisig` signature if a key is specified
+    /// * `<out>/<version>/SHA256SUMS`
+    /// * `<out>/manifest.json` updated with the release
+    pub fn run(&self, paths: &[PathBuf], key: Option<&SecretKey>) -> Result<(), Box<dyn Error>> {
+        let dir = self.out.join(self.version.to_string());
+        std::fs::create_dir_all(&dir)?;
+
+        let mut artifacts = Vec::new();
+        for path in paths {
+            let artifact = Artifact::read(path)?;
+            if artifacts.iter().any(|a: &Artifact| a.name == artifact.name) {
+                return Err(format!("Duplicate artifact name: {}", artifact.name).into());
+            }
+
+            std::fs::copy(path, dir.join(&artifact.name))?;
+            if let Some(key) = key {
+                std::fs::write(artifact.signature_path(&dir), artifact.sign(key))?;
+            }
+            println!(
+                "{}  {} ({} bytes)",
+                artifact.sha256, artifact.name, artifact.size
+            );
+
+            artifacts.push(artifact);
+        }
+        std::fs::write(dir.join(CHECKSUMS), checksums(&artifacts))?;
+
+        let manifest_path = self.out.join("manifest.json");
+        let mut manifest = Manifest::load(&manifest_path)?;
+        manifest.add(Release {
+            version: self.version.clone(),
+            channel: self.channel.clone(),
+            notes: self.notes.clone(),
+            assets: artifacts
+                .iter()
+                .map(|a| manifest::Asset {
+                    name: a.name.clone(),
+                    size: a.size,
+                    url: self.url(&a.name),
+                    sha256: Some(a.sha256.clone()),
+                })
+                .collect(),
+        });
+        manifest.save(&manifest_path)?;
+
+        Ok(())
+    }
+
+    fn url(&self, name: &str) -> String {
+        let path = format!("{}/{}", self.version, name);
+        match self.base_url.as_ref() {
+            Some(base) => format!("{}/{}", base.trim_end_matches('/'), path),
+            None => path,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use updater::provider::{DirectoryProvider, DownloadResult, Provider};
+    use updater::signature::{self, PublicKey};
+    use updater::Progress;
+
+    fn directory(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir_all(dir.join("build")).unwrap();
+        dir
+    }
+
+    #[test]
+    fn hash_artifact() {
+        let dir = directory("updater_publish_test_hash");
+        let path = dir.join("build").join("app.zip");
+        std::fs::write(&path, "hello").unwrap();
+
+        let artifact = Artifact::read(&path).unwrap();
+        assert_eq!(artifact.name, "app.zip");
+        assert_eq!(artifact.size, 5);
+        assert_eq!(
+            checksums(&[artifact]),
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824  app.zip\n"
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn publish_release() {
+        let dir = directory("updater_publish_test_release");
+        let artifact = dir.join("build").join("app-linux-x64.tar.gz");
+        std::fs::write(&artifact, "release content").unwrap();
+        let key = SecretKey::generate();
+
+        let out = dir.join("public");
+        let publish = Publish {
+            version: Version::new(1, 2, 0),
+            channel: Some("stable".into()),
+            notes: Some("Bug fixes".into()),
+            base_url: None,
+            out: out.clone(),
+        };
+        publish
+            .run(std::slice::from_ref(&artifact), Some(&key))
+            .unwrap();
+        Publish {
+            version: Version::new(1, 1, 0),
+            base_url: Some("https://cdn.example.com/app/".into()),
+            ..publish
+        }
+        .run(&[artifact], None)
+        .unwrap();
+
+        let manifest = Manifest::load(&out.join("manifest.json")).unwrap();
+        assert_eq!(manifest.releases.len(), 2);
+        assert_eq!(
+            manifest.releases[0].assets[0].url,
+            "1.2.0/app-linux-x64.tar.gz"
+        );
+        assert_eq!(
+            manifest.releases[1].assets[0].url,
+            "https://cdn.example.com/app/1.1.0/app-linux-x64.tar.gz"
+        );
+        assert!(!out
+            .join("1.1.0")
+            .join("app-linux-x64.tar.gz.minisig")
+            .exists());
+
+        // The published directory is consumable by the updater
+        let mut provider = DirectoryProvider::new(out.to_str().unwrap());
+        provider.fetch().unwrap();
+        let version = provider.latest().unwrap();
+        assert_eq!(version, Version::new(1, 2, 0));
+
+        let mut asset = provider.asset(&version, "app-linux-x64.tar.gz").unwrap();
+        let sha256 = provider.checksum(&version, asset.name()).unwrap().unwrap();
+        assert_eq!(
+            Some(sha256.as_str()),
+            manifest.releases[0].assets[0].sha256.as_deref()
+        );
+        asset.set_sha256(sha256);
+
+        let mut file = match asset.download(Arc::new(Progress::default())) {
+            DownloadResult::Complete(file) => file,
+            result => panic!("Unexpected download result: {:?}", result),
+        };
+        let public_key: PublicKey = key.public_key().to_base64().parse().unwrap();
+        signature::verify_asset(
+            &provider,
+            &version,
+            asset.name(),
+            &mut file,
+            &public_key,
+            false,
+        )
+        .unwrap();
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
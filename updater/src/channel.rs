@@ -0,0 +1,100 @@
+use semver::Version;
+use serde::Deserialize;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Release channel. Ordered from the most to the least stable.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    #[default]
+    Stable,
+    Beta,
+    Nightly,
+}
+
+impl Channel {
+    /// Derives the channel from the prerelease part of the version.
+    ///
+    /// * no prerelease: `Stable`
+    /// * "nightly", "dev" or "snapshot" prerelease: `Nightly`
+    /// * any other prerelease (eg. "alpha", "beta", "rc"): `Beta`
+    pub fn from_version(version: &Version) -> Self {
+        let pre = version.pre.as_str();
+        if pre.is_empty() {
+            Channel::Stable
+        } else if ["nightly", "dev", "snapshot"]
+            .iter()
+            .any(|prefix| pre.starts_with(prefix))
+        {
+            Channel::Nightly
+        } else {
+            Channel::Beta
+        }
+    }
+
+    /// Checks if releases of the other channel are available in this channel.
+    /// A channel includes itself and every more stable channel.
+    pub fn includes(&self, other: Channel) -> bool {
+        other <= *self
+    }
+}
+
+impl FromStr for Channel {
+    type Err = Box<dyn Error>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "stable" => Ok(Channel::Stable),
+            "beta" => Ok(Channel::Beta),
+            "nightly" => Ok(Channel::Nightly),
+            _ => Err(format!("Unknown channel: {}", s).into()),
+        }
+    }
+}
+
+impl Display for Channel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Channel::Stable => "stable",
+            Channel::Beta => "beta",
+            Channel::Nightly => "nightly",
+        };
+        f.write_str(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(version: &str) -> Channel {
+        Channel::from_version(&Version::parse(version).unwrap())
+    }
+
+    #[test]
+    fn from_version() {
+        assert_eq!(channel("1.2.3"), Channel::Stable);
+        assert_eq!(channel("1.2.3-beta.1"), Channel::Beta);
+        assert_eq!(channel("1.2.3-rc2"), Channel::Beta);
+        assert_eq!(channel("1.2.3-nightly.20240101"), Channel::Nightly);
+        assert_eq!(channel("1.2.3-dev"), Channel::Nightly);
+    }
+
+    #[test]
+    fn includes() {
+        assert!(Channel::Stable.includes(Channel::Stable));
+        assert!(!Channel::Stable.includes(Channel::Beta));
+        assert!(Channel::Beta.includes(Channel::Stable));
+        assert!(!Channel::Beta.includes(Channel::Nightly));
+        assert!(Channel::Nightly.includes(Channel::Beta));
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("Beta".parse::<Channel>().unwrap(), Channel::Beta);
+        assert_eq!(Channel::Nightly.to_string(), "nightly");
+        assert!("unstable".parse::<Channel>().is_err());
+    }
+}
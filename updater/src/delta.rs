@@ -0,0 +1,437 @@
+//! Binary delta updates between two versions of an installed application.
+//!
+//! A patch is an uncompressed tar archive with the following entries:
+//! * `patch.json`: the list of files of the new version (always the first entry)
+//! * `files/<path>`: the zstd compressed content of the changed and new files.
+//!   Changed files are compressed with the old file as the reference prefix
+//!   (like `zstd --patch-from`), so only the difference is stored.
+//!
+//! Unchanged files are copied from the old install directory.
+//! Every file of the new version is verified against its SHA-256 hash.
+
+use crate::provider::{copy_with_progress, HashWriter};
+use crate::sync::{hash_file, list_files};
+use crate::Progress;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// Name of the file list entry
+const INDEX: &str = "patch.json";
+/// Directory of the file content entries
+const FILES: &str = "files/";
+/// Largest window the decoder accepts (64 MiB)
+const WINDOW_LOG_MAX: u32 = 26;
+/// Old files are loaded into memory as the reference of their patch,
+/// so changed files bigger than this are stored in full.
+const MAX_REFERENCE_SIZE: u64 = 1 << WINDOW_LOG_MAX;
+/// Compression level of the patches
+const LEVEL: i32 = 19;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PatchResult {
+    Complete,
+    Cancelled,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Index {
+    files: Vec<IndexEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IndexEntry {
+    /// Path relative to the install directory, separated by '/'
+    path: String,
+    size: u64,
+    sha256: String,
+    source: Source,
+    /// Unix permissions of the file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mode: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Source {
+    /// Unchanged, copied from the old version
+    Copy,
+    /// Compressed with the old file as reference
+    Patch,
+    /// Compressed without reference
+    Full,
+}
+
+/// Applies the patch to the install directory of the old version.
+/// The new version is written into the (empty or missing) target directory.
+pub fn apply<R: Read>(
+    patch: R,
+    old: &Path,
+    target: &Path,
+    progress: Arc<Progress>,
+) -> Result<PatchResult, Box<dyn Error>> {
+    let mut archive = tar::Archive::new(patch);
+    let mut entries = archive.entries()?;
+
+    // Read the file list
+    let index: Index = match entries.next() {
+        Some(entry) => {
+            let entry = entry?;
+            if entry.path()?.to_str() != Some(INDEX) {
+                return Err("Invalid patch: missing file list".into());
+            }
+            json::from_reader(entry)?
+        }
+        None => return Err("Invalid patch: empty archive".into()),
+    };
+
+    let mut pending = HashMap::new();
+    for file in index.files.iter() {
+        safe_path(&file.path)?;
+        if file.source != Source::Copy {
+            pending.insert(file.path.as_str(), file);
+        }
+    }
+
+    progress.set_maximum(index.files.iter().map(|f| f.size).sum());
+    progress.set_indeterminate(false);
+    std::fs::create_dir_all(target)?;
+
+    // Copy unchanged files
+    for file in index.files.iter().filter(|f| f.source == Source::Copy) {
+        let mut reader = File::open(old.join(safe_path(&file.path)?))?;
+        if !write_file(&mut reader, target, file, &progress)? {
+            return Ok(PatchResult::Cancelled);
+        }
+    }
+
+    // Decompress changed and new files
+    for entry in entries {
+        let entry = entry?;
+        let path = entry.path()?.to_string_lossy().into_owned();
+        let file = match path.strip_prefix(FILES).and_then(|p| pending.remove(p)) {
+            Some(file) => file,
+            None => return Err(format!("Invalid patch: unexpected entry {}", path).into()),
+        };
+
+        // Old content to decompress against
+        let reference = match file.source {
+            Source::Patch => read_reference(&old.join(safe_path(&file.path)?))?,
+            _ => Vec::new(),
+        };
+
+        let mut decoder =
+            zstd::stream::read::Decoder::with_ref_prefix(BufReader::new(entry), &reference)?;
+        decoder.window_log_max(WINDOW_LOG_MAX)?;
+        if !write_file(&mut decoder, target, file, &progress)? {
+            return Ok(PatchResult::Cancelled);
+        }
+    }
+
+    if let Some(path) = pending.keys().next() {
+        return Err(format!("Invalid patch: missing content of {}", path).into());
+    }
+
+    Ok(PatchResult::Complete)
+}
+
+/// Writes the file into the target directory and verifies it. Returns false if cancelled.
+fn write_file<R: Read>(
+    reader: &mut R,
+    target: &Path,
+    file: &IndexEntry,
+    progress: &Progress,
+) -> Result<bool, Box<dyn Error>> {
+    let path = target.join(safe_path(&file.path)?);
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut writer = HashWriter::new(File::create(&path)?);
+    if !copy_with_progress(reader, &mut writer, progress)? {
+        return Ok(false);
+    }
+    writer
+        .verify(&file.sha256)
+        .map_err(|e| format!("{}: {}", file.path, e))?;
+
+    #[cfg(unix)]
+    if let Some(mode) = file.mode {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode))?;
+    }
+
+    Ok(true)
+}
+
+/// Creates a patch which updates the old install directory to the new one.
+pub fn create<W: Write>(old: &Path, new: &Path, writer: W) -> Result<W, Box<dyn Error>> {
+    let mut paths = Vec::new();
+    list_files(new, "", &mut paths)?;
+
+    // Decide how to store each file
+    let mut index = Index { files: Vec::new() };
+    for path in paths {
+        let new_path = new.join(&path);
+        let metadata = new_path.metadata()?;
+        let sha256 = hash_file(&new_path)?;
+
+        let old_path = old.join(&path);
+        let source = if !old_path.is_file() {
+            Source::Full
+        } else if hash_file(&old_path)? == sha256 {
+            Source::Copy
+        } else if old_path.metadata()?.len().max(metadata.len()) >= MAX_REFERENCE_SIZE {
+            Source::Full
+        } else {
+            Source::Patch
+        };
+
+        index.files.push(IndexEntry {
+            path,
+            size: metadata.len(),
+            sha256,
+            source,
+            mode: mode(&metadata),
+        });
+    }
+
+    let mut builder = tar::Builder::new(writer);
+    let content = json::to_vec_pretty(&index)?;
+    append(
+        &mut builder,
+        INDEX,
+        &mut content.as_slice(),
+        content.len() as u64,
+    )?;
+
+    for file in index.files.iter().filter(|f| f.source != Source::Copy) {
+        let reference = match file.source {
+            Source::Patch => read_reference(&old.join(&file.path))?,
+            _ => Vec::new(),
+        };
+
+        // Compress into a temporary file as the size is needed before the content
+        let mut encoder = zstd::stream::write::Encoder::with_ref_prefix(
+            tempfile::tempfile()?,
+            LEVEL,
+            &reference,
+        )?;
+        if !reference.is_empty() {
+            encoder.long_distance_matching(true)?;
+            encoder.window_log(window_log(reference.len().max(file.size as usize)))?;
+        }
+        std::io::copy(&mut File::open(new.join(&file.path))?, &mut encoder)?;
+        let mut compressed = encoder.finish()?;
+
+        let size = compressed.stream_position()?;
+        compressed.seek(SeekFrom::Start(0))?;
+        append(
+            &mut builder,
+            &format!("{}{}", FILES, file.path),
+            &mut compressed,
+            size,
+        )?;
+    }
+
+    Ok(builder.into_inner()?)
+}
+
+fn append<W: Write, R: Read>(
+    builder: &mut tar::Builder<W>,
+    path: &str,
+    content: &mut R,
+    size: u64,
+) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(size);
+    header.set_mode(0o644);
+    builder.append_data(&mut header, path, content)
+}
+
+/// Reads the old file the content is compressed against
+fn read_reference(path: &Path) -> Result<Vec<u8>, Box<dyn Error>> {
+    let file = File::open(path)?;
+    if file.metadata()?.len() >= MAX_REFERENCE_SIZE {
+        return Err(format!("{} is too big to be patched", path.display()).into());
+    }
+
+    let mut content = Vec::new();
+    file.take(MAX_REFERENCE_SIZE).read_to_end(&mut content)?;
+    Ok(content)
+}
+
+/// Smallest window log which covers the size
+fn window_log(size: usize) -> u32 {
+    (usize::BITS - size.leading_zeros()).clamp(10, WINDOW_LOG_MAX)
+}
+
+#[cfg(unix)]
+fn mode(metadata: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+fn mode(_: &std::fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// Checks that the path stays inside the install directory
+fn safe_path(path: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let path = Path::new(path);
+    match path.components().all(|c| matches!(c, Component::Normal(_))) {
+        true => Ok(path.to_path_buf()),
+        false => Err(format!("Invalid patch: disallowed path {}", path.display()).into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directory(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, path: &str, content: &[u8]) {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    /// Creates two versions and the patch between them
+    fn versions(dir: &Path) -> Vec<u8> {
+        let binary: Vec<u8> = (0..200_000u32)
+            .flat_map(|i| (i / 7).to_le_bytes())
+            .collect();
+        let mut changed = binary.clone();
+        changed[1000..1100].fill(0xff);
+
+        let old = dir.join("1.0.0");
+        write(&old, "app.bin", &binary);
+        write(&old, "data/unchanged.txt", b"same in both versions");
+        write(&old, "data/removed.txt", b"only in the old version");
+
+        let new = dir.join("1.1.0");
+        write(&new, "app.bin", &changed);
+        write(&new, "data/unchanged.txt", b"same in both versions");
+        write(&new, "data/nested/added.txt", b"only in the new version");
+
+        create(&old, &new, Vec::new()).unwrap()
+    }
+
+    #[test]
+    fn create_and_apply() {
+        let dir = directory("updater_delta_test_apply");
+        let patch = versions(&dir);
+        // Only the difference is stored
+        assert!(patch.len() < 100_000);
+
+        let target = dir.join("patched");
+        let progress = Arc::new(Progress::default());
+        let result = apply(
+            patch.as_slice(),
+            &dir.join("1.0.0"),
+            &target,
+            progress.clone(),
+        );
+        assert_eq!(result.unwrap(), PatchResult::Complete);
+
+        for path in ["app.bin", "data/unchanged.txt", "data/nested/added.txt"] {
+            assert_eq!(
+                std::fs::read(target.join(path)).unwrap(),
+                std::fs::read(dir.join("1.1.0").join(path)).unwrap()
+            );
+        }
+        assert!(!target.join("data/removed.txt").exists());
+        assert_eq!(progress.current(), progress.maximum());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn modified_install() {
+        let dir = directory("updater_delta_test_modified");
+        let patch = versions(&dir);
+
+        // The patched result doesn't match if the old install was modified
+        write(&dir.join("1.0.0"), "app.bin", b"modified locally");
+        let result = apply(
+            patch.as_slice(),
+            &dir.join("1.0.0"),
+            &dir.join("patched"),
+            Arc::new(Progress::default()),
+        );
+        assert!(result.is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cancelled() {
+        let dir = directory("updater_delta_test_cancel");
+        let patch = versions(&dir);
+
+        let progress = Arc::new(Progress::default());
+        progress.set_cancelled(true);
+        let result = apply(
+            patch.as_slice(),
+            &dir.join("1.0.0"),
+            &dir.join("patched"),
+            progress,
+        );
+        assert_eq!(result.unwrap(), PatchResult::Cancelled);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_patches() {
+        let dir = directory("updater_delta_test_invalid");
+        let target = dir.join("patched");
+        let progress = Arc::new(Progress::default());
+
+        // Not a patch
+        assert!(apply(&b"garbage"[..], &dir, &target, progress.clone()).is_err());
+
+        // Path outside of the install directory
+        let index =
+            br#"{ "files": [{ "path": "../evil", "size": 1, "sha256": "", "source": "full" }] }"#;
+        let mut builder = tar::Builder::new(Vec::new());
+        append(&mut builder, INDEX, &mut &index[..], index.len() as u64).unwrap();
+        let patch = builder.into_inner().unwrap();
+        assert!(apply(patch.as_slice(), &dir, &target, progress).is_err());
+        assert!(!dir.parent().unwrap().join("evil").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn big_reference() {
+        let dir = directory("updater_delta_test_big");
+        let path = dir.join("app.bin");
+        write(&dir, "app.bin", b"small");
+        assert_eq!(read_reference(&path).unwrap(), b"small");
+
+        // Not loaded into memory
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(MAX_REFERENCE_SIZE)
+            .unwrap();
+        assert!(read_reference(&path).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
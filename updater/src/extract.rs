@@ -1,8 +1,22 @@
+//! Archive extraction.
+//!
+//! The format of an asset is detected by its first bytes, with its name as a hint
+//! (and as the only way to detect formats without magic bytes).
+//! Additional formats can be supported by registering an [`Extractor`]:
+//! ```ignore
+//! updater::extract::register(MyPackExtractor);
+//! ```
+
 use crate::Progress;
+use lazy_static::lazy_static;
 use std::error::Error;
 use std::fs::File;
-use std::path::Path;
-use std::sync::Arc;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Number of bytes from the start of the archive used for the detection
+pub const HEADER_LEN: usize = 512;
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum ExtractResult {
@@ -10,23 +24,239 @@ pub enum ExtractResult {
     Cancelled,
 }
 
+/// Options to select what's extracted from the archive and where to
+#[derive(Debug, Clone, Default)]
+pub struct ExtractOptions {
+    strip_components: usize,
+    subdirectory: Option<PathBuf>,
+    include: Vec<glob::Pattern>,
+}
+
+impl ExtractOptions {
+    /// Removes the given number of leading directories from the paths
+    /// (like `tar --strip-components`). Entries which don't have more are skipped.
+    pub fn with_strip_components(mut self, strip_components: usize) -> Self {
+        self.strip_components = strip_components;
+        self
+    }
+
+    /// Extracts only the content of this directory of the archive (after stripping)
+    pub fn with_subdirectory<P: AsRef<Path>>(mut self, subdirectory: P) -> Self {
+        let subdirectory: PathBuf = subdirectory
+            .as_ref()
+            .components()
+            .filter(|c| *c != Component::CurDir)
+            .collect();
+        self.subdirectory = Some(subdirectory).filter(|dir| !dir.as_os_str().is_empty());
+        self
+    }
+
+    /// Extracts only the files matching any of the globs (eg. "bin/*" or "**/*.dll").
+    /// The globs are matched against the paths inside the target directory.
+    pub fn with_include<S: AsRef<str>>(mut self, patterns: &[S]) -> Result<Self, Box<dyn Error>> {
+        self.include = patterns
+            .iter()
+            .map(|pattern| glob::Pattern::new(pattern.as_ref()))
+            .collect::<Result<_, _>>()?;
+        Ok(self)
+    }
+
+    /// Maps the path inside the archive to the path inside the target. None if it's skipped.
+    fn map(&self, path: &Path) -> Option<PathBuf> {
+        let path: PathBuf = path
+            .components()
+            .filter(|c| *c != Component::CurDir)
+            .skip(self.strip_components)
+            .collect();
+        let path = match self.subdirectory.as_ref() {
+            Some(subdirectory) => path.strip_prefix(subdirectory).ok()?.to_path_buf(),
+            None => path,
+        };
+        Some(path).filter(|path| !path.as_os_str().is_empty())
+    }
+
+    /// Checks if the file is included by the globs
+    fn includes(&self, path: &Path) -> bool {
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|pattern| pattern.matches_path_with(path, options))
+    }
+}
+
+/// Selects the entries of the archive to extract and maps them into the target directory
+pub struct Selection<'a> {
+    options: &'a ExtractOptions,
+    filter: &'a dyn Fn(&Path) -> bool,
+}
+
+impl<'a> Selection<'a> {
+    /// Creates the selection from the options and a filter,
+    /// which gets the paths of the files inside the target
+    pub fn new(options: &'a ExtractOptions, filter: &'a dyn Fn(&Path) -> bool) -> Self {
+        Self { options, filter }
+    }
+
+    /// Gets the path of the directory inside the target. None if it's skipped.
+    pub fn dir(&self, path: &Path) -> Option<PathBuf> {
+        self.options.map(path)
+    }
+
+    /// Gets the path of the file (or link) inside the target. None if it's skipped.
+    pub fn file(&self, path: &Path) -> Option<PathBuf> {
+        let path = self.options.map(path)?;
+        (self.options.includes(&path) && (self.filter)(&path)).then_some(path)
+    }
+}
+
+/// Extractor of an archive format
+pub trait Extractor: Send + Sync {
+    /// Name of the format (eg. "zip")
+    fn name(&self) -> &str;
+
+    /// Checks the name of the asset (eg. its extension)
+    fn matches_name(&self, name: &str) -> bool;
+
+    /// Checks the first bytes of the archive (at most `HEADER_LEN`)
+    fn matches_header(&self, header: &[u8]) -> bool;
+
+    /// Extracts the entries of the archive chosen by the selection
+    fn extract(
+        &self,
+        archive: File,
+        target: &Path,
+        progress: Arc<Progress>,
+        select: &Selection,
+    ) -> Result<ExtractResult, Box<dyn Error>>;
+
+    /// Checks if the format can be extracted while it's read
+    fn can_stream(&self) -> bool {
+        false
+    }
+
+    /// Extracts the archive while it's read. The progress is reported by the reader.
+    fn extract_stream(
+        &self,
+        archive: &mut dyn Read,
+        target: &Path,
+        progress: Arc<Progress>,
+        select: &Selection,
+    ) -> Result<ExtractResult, Box<dyn Error>> {
+        let _ = (archive, target, progress, select);
+        Err(format!("{} can't be extracted while downloading", self.name()).into())
+    }
+}
+
+lazy_static! {
+    static ref EXTRACTORS: RwLock<Vec<Arc<dyn Extractor>>> = RwLock::new(builtin());
+}
+
+/// The extractors of the enabled formats
+#[allow(clippy::vec_init_then_push)]
+fn builtin() -> Vec<Arc<dyn Extractor>> {
+    #[allow(unused_mut)]
+    let mut extractors: Vec<Arc<dyn Extractor>> = Vec::new();
+
+    #[cfg(feature = "ext-zip")]
+    extractors.push(Arc::new(ZipExtractor));
+
+    #[cfg(feature = "ext-targz")]
+    extractors.push(Arc::new(TarExtractor(TarFormat::Gz)));
+    #[cfg(feature = "ext-tarxz")]
+    extractors.push(Arc::new(TarExtractor(TarFormat::Xz)));
+    #[cfg(feature = "ext-tarzst")]
+    extractors.push(Arc::new(TarExtractor(TarFormat::Zst)));
+    #[cfg(feature = "ext-tarbz2")]
+    extractors.push(Arc::new(TarExtractor(TarFormat::Bz2)));
+
+    extractors
+}
+
+/// Registers an extractor for an additional format.
+/// It takes precedence over the previously registered and the built-in extractors.
+pub fn register<E: Extractor + 'static>(extractor: E) {
+    EXTRACTORS.write().unwrap().insert(0, Arc::new(extractor));
+}
+
+/// Finds the extractor of the archive by its first bytes, using the name as a hint.
+/// Falls back to the name for formats without known magic bytes.
+pub fn detect(name: &str, header: &[u8]) -> Option<Arc<dyn Extractor>> {
+    let extractors = EXTRACTORS.read().unwrap();
+
+    let by_header = || extractors.iter().filter(|e| e.matches_header(header));
+    by_header()
+        .find(|e| e.matches_name(name))
+        .or_else(|| by_header().next())
+        .or_else(|| extractors.iter().find(|e| e.matches_name(name)))
+        .cloned()
+}
+
 pub fn asset<P: AsRef<Path>>(
     name: &str,
     archive: File,
     target: P,
     progress: Arc<Progress>,
 ) -> Result<ExtractResult, Box<dyn Error>> {
-    #[cfg(feature = "ext-zip")]
-    if name.ends_with(".zip") {
-        return zip(archive, target, progress);
-    }
+    let options = ExtractOptions::default();
+    asset_filtered(name, archive, target, progress, &options, |_| true)
+}
 
-    #[cfg(feature = "ext-targz")]
-    if name.ends_with(".tar.gz") {
-        return targz(archive, target, progress);
-    }
+/// Extracts the files of the asset selected by the options and accepted by the filter.
+/// The filter gets the path of the file inside the target.
+pub fn asset_filtered<P: AsRef<Path>, F: Fn(&Path) -> bool>(
+    name: &str,
+    mut archive: File,
+    target: P,
+    progress: Arc<Progress>,
+    options: &ExtractOptions,
+    filter: F,
+) -> Result<ExtractResult, Box<dyn Error>> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    (&mut archive)
+        .take(HEADER_LEN as u64)
+        .read_to_end(&mut header)?;
+    archive.seek(SeekFrom::Start(0))?;
 
-    Err("Unknown archive format!".into())
+    let extractor = detect(name, &header).ok_or("Unknown archive format!")?;
+    log::info!("Extracting {} as {}", name, extractor.name());
+    let select = Selection::new(options, &filter);
+    extractor.extract(archive, target.as_ref(), progress, &select)
+}
+
+/// Checks if the asset can be extracted while it's read (eg. while downloading it).
+/// Only the name is known at that point.
+pub fn can_stream(name: &str) -> bool {
+    let extractors = EXTRACTORS.read().unwrap();
+    extractors
+        .iter()
+        .find(|e| e.matches_name(name))
+        .is_some_and(|e| e.can_stream())
+}
+
+/// Extracts the selected files of the asset while it's read (see `asset_filtered`).
+/// Only tar based formats can be streamed (see `can_stream`).
+pub fn stream<R: Read, P: AsRef<Path>, F: Fn(&Path) -> bool>(
+    name: &str,
+    mut reader: R,
+    target: P,
+    progress: Arc<Progress>,
+    options: &ExtractOptions,
+    filter: F,
+) -> Result<ExtractResult, Box<dyn Error>> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    (&mut reader)
+        .take(HEADER_LEN as u64)
+        .read_to_end(&mut header)?;
+
+    let extractor = detect(name, &header).ok_or("Unknown archive format!")?;
+    let mut archive = Cursor::new(header).chain(reader);
+    let select = Selection::new(options, &filter);
+    extractor.extract_stream(&mut archive, target.as_ref(), progress, &select)
 }
 
 #[cfg(feature = "ext-zip")]
@@ -34,6 +264,18 @@ pub fn zip<P: AsRef<Path>>(
     zip: File,
     target: P,
     progress: Arc<Progress>,
+) -> Result<ExtractResult, Box<dyn Error>> {
+    let options = ExtractOptions::default();
+    let select = Selection::new(&options, &|_| true);
+    extract_zip(zip, target.as_ref(), progress, &select)
+}
+
+#[cfg(feature = "ext-zip")]
+fn extract_zip(
+    zip: File,
+    target: &Path,
+    progress: Arc<Progress>,
+    select: &Selection,
 ) -> Result<ExtractResult, Box<dyn Error>> {
     use zip::ZipArchive;
 
@@ -41,7 +283,13 @@ pub fn zip<P: AsRef<Path>>(
     let mut size = 0;
 
     for i in 0..archive.len() {
-        size += archive.by_index(i)?.size();
+        let item = archive.by_index(i)?;
+        if item
+            .enclosed_name()
+            .is_some_and(|path| zip_selection(select, &path, item.is_dir()).is_some())
+        {
+            size += item.size();
+        }
     }
 
     progress.add_maximum(size);
@@ -54,7 +302,10 @@ pub fn zip<P: AsRef<Path>>(
 
         let mut zipped_item = archive.by_index(i)?;
         let item_path = zipped_item.enclosed_name().ok_or("Disallowed path")?;
-        let out_path = target.as_ref().join(item_path);
+        let out_path = match zip_selection(select, &item_path, zipped_item.is_dir()) {
+            Some(path) => target.join(path),
+            None => continue,
+        };
 
         if zipped_item.is_dir() {
             std::fs::create_dir_all(&out_path)?;
@@ -85,25 +336,847 @@ pub fn zip<P: AsRef<Path>>(
     Ok(ExtractResult::Complete)
 }
 
+#[cfg(feature = "ext-zip")]
+fn zip_selection(select: &Selection, path: &Path, is_dir: bool) -> Option<PathBuf> {
+    match is_dir {
+        true => select.dir(path),
+        false => select.file(path),
+    }
+}
+
+#[cfg(feature = "ext-zip")]
+struct ZipExtractor;
+
+#[cfg(feature = "ext-zip")]
+impl Extractor for ZipExtractor {
+    fn name(&self) -> &str {
+        "zip"
+    }
+
+    fn matches_name(&self, name: &str) -> bool {
+        name.ends_with(".zip")
+    }
+
+    fn matches_header(&self, header: &[u8]) -> bool {
+        // Local file header or the end of an empty archive
+        header.starts_with(b"PK\x03\x04") || header.starts_with(b"PK\x05\x06")
+    }
+
+    fn extract(
+        &self,
+        archive: File,
+        target: &Path,
+        progress: Arc<Progress>,
+        select: &Selection,
+    ) -> Result<ExtractResult, Box<dyn Error>> {
+        extract_zip(archive, target, progress, select)
+    }
+}
+
 #[cfg(feature = "ext-targz")]
 pub fn targz<P: AsRef<Path>>(
     targz: File,
     target: P,
     progress: Arc<Progress>,
 ) -> Result<ExtractResult, Box<dyn Error>> {
-    use flate2::read::GzDecoder;
+    let options = ExtractOptions::default();
+    let select = Selection::new(&options, &|_| true);
+    TarExtractor(TarFormat::Gz).extract(targz, target.as_ref(), progress, &select)
+}
+
+#[cfg(feature = "ext-tarxz")]
+pub fn tarxz<P: AsRef<Path>>(
+    tarxz: File,
+    target: P,
+    progress: Arc<Progress>,
+) -> Result<ExtractResult, Box<dyn Error>> {
+    let options = ExtractOptions::default();
+    let select = Selection::new(&options, &|_| true);
+    TarExtractor(TarFormat::Xz).extract(tarxz, target.as_ref(), progress, &select)
+}
+
+#[cfg(feature = "ext-tarzst")]
+pub fn tarzst<P: AsRef<Path>>(
+    tarzst: File,
+    target: P,
+    progress: Arc<Progress>,
+) -> Result<ExtractResult, Box<dyn Error>> {
+    let options = ExtractOptions::default();
+    let select = Selection::new(&options, &|_| true);
+    TarExtractor(TarFormat::Zst).extract(tarzst, target.as_ref(), progress, &select)
+}
+
+#[cfg(feature = "ext-tarbz2")]
+pub fn tarbz2<P: AsRef<Path>>(
+    tarbz2: File,
+    target: P,
+    progress: Arc<Progress>,
+) -> Result<ExtractResult, Box<dyn Error>> {
+    let options = ExtractOptions::default();
+    let select = Selection::new(&options, &|_| true);
+    TarExtractor(TarFormat::Bz2).extract(tarbz2, target.as_ref(), progress, &select)
+}
+
+/// Compression of a tar archive
+#[cfg(feature = "ext-tar")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TarFormat {
+    #[cfg(feature = "ext-targz")]
+    Gz,
+    #[cfg(feature = "ext-tarxz")]
+    Xz,
+    #[cfg(feature = "ext-tarzst")]
+    Zst,
+    #[cfg(feature = "ext-tarbz2")]
+    Bz2,
+}
+
+#[cfg(feature = "ext-tar")]
+impl TarFormat {
+    /// Extensions of the format
+    fn extensions(self) -> &'static [&'static str] {
+        match self {
+            #[cfg(feature = "ext-targz")]
+            Self::Gz => &[".tar.gz", ".tgz"],
+            #[cfg(feature = "ext-tarxz")]
+            Self::Xz => &[".tar.xz", ".txz"],
+            #[cfg(feature = "ext-tarzst")]
+            Self::Zst => &[".tar.zst", ".tzst"],
+            #[cfg(feature = "ext-tarbz2")]
+            Self::Bz2 => &[".tar.bz2", ".tbz2"],
+        }
+    }
+
+    /// Magic bytes of the compression
+    fn magic(self) -> &'static [u8] {
+        match self {
+            #[cfg(feature = "ext-targz")]
+            Self::Gz => &[0x1f, 0x8b],
+            #[cfg(feature = "ext-tarxz")]
+            Self::Xz => &[0xfd, b'7', b'z', b'X', b'Z', 0x00],
+            #[cfg(feature = "ext-tarzst")]
+            Self::Zst => &[0x28, 0xb5, 0x2f, 0xfd],
+            #[cfg(feature = "ext-tarbz2")]
+            Self::Bz2 => b"BZh",
+        }
+    }
+
+    /// Wraps the compressed archive into its decoder
+    fn decoder<'a, R: Read + 'a>(self, archive: R) -> Result<Box<dyn Read + 'a>, Box<dyn Error>> {
+        Ok(match self {
+            #[cfg(feature = "ext-targz")]
+            Self::Gz => Box::new(flate2::read::GzDecoder::new(archive)),
+            #[cfg(feature = "ext-tarxz")]
+            Self::Xz => Box::new(xz2::read::XzDecoder::new(archive)),
+            #[cfg(feature = "ext-tarzst")]
+            Self::Zst => {
+                let mut decoder = zstd::stream::read::Decoder::new(archive)?;
+                // Allow archives compressed in long distance mode (`zstd --long`)
+                decoder.window_log_max(31)?;
+                Box::new(decoder)
+            }
+            #[cfg(feature = "ext-tarbz2")]
+            Self::Bz2 => Box::new(bzip2::read::BzDecoder::new(archive)),
+        })
+    }
+}
+
+/// Extractor of a compressed tar archive
+#[cfg(feature = "ext-tar")]
+struct TarExtractor(TarFormat);
+
+#[cfg(feature = "ext-tar")]
+impl Extractor for TarExtractor {
+    fn name(&self) -> &str {
+        &self.0.extensions()[0][1..]
+    }
+
+    fn matches_name(&self, name: &str) -> bool {
+        self.0.extensions().iter().any(|ext| name.ends_with(ext))
+    }
+
+    fn matches_header(&self, header: &[u8]) -> bool {
+        header.starts_with(self.0.magic())
+    }
+
+    fn extract(
+        &self,
+        archive: File,
+        target: &Path,
+        progress: Arc<Progress>,
+        select: &Selection,
+    ) -> Result<ExtractResult, Box<dyn Error>> {
+        // Progress is based on the position in the compressed archive
+        progress.add_maximum(archive.metadata()?.len());
+        progress.set_indeterminate(false);
+
+        let archive = ProgressReader {
+            inner: archive,
+            progress: progress.clone(),
+        };
+        extract_tar(self.0, archive, target, progress, select)
+    }
+
+    fn can_stream(&self) -> bool {
+        true
+    }
+
+    fn extract_stream(
+        &self,
+        archive: &mut dyn Read,
+        target: &Path,
+        progress: Arc<Progress>,
+        select: &Selection,
+    ) -> Result<ExtractResult, Box<dyn Error>> {
+        extract_tar(self.0, archive, target, progress, select)
+    }
+}
+
+#[cfg(feature = "ext-tar")]
+fn extract_tar<R: Read>(
+    format: TarFormat,
+    archive: R,
+    target: &Path,
+    progress: Arc<Progress>,
+    select: &Selection,
+) -> Result<ExtractResult, Box<dyn Error>> {
     use tar::Archive as TarArchive;
 
-    let tar = GzDecoder::new(targz);
-    let mut archive = TarArchive::new(tar);
+    let mut archive = TarArchive::new(format.decoder(archive)?);
+    let root = target.canonicalize()?;
 
     for entry in archive.entries()? {
         if progress.cancelled() {
             return Ok(ExtractResult::Cancelled);
         }
 
-        entry?.unpack_in(&target)?;
+        let mut entry = entry?;
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_pax_global_extensions() {
+            continue;
+        }
+
+        let path = entry.path()?.into_owned();
+        let is_normal = |c: Component| matches!(c, Component::Normal(_) | Component::CurDir);
+        if !path.components().all(is_normal) {
+            return Err(format!("Disallowed path: {}", path.display()).into());
+        }
+        let out_path = match entry_type.is_dir() {
+            true => select.dir(&path),
+            false => select.file(&path),
+        };
+        let out_path = match out_path {
+            Some(out_path) => out_path,
+            None => continue,
+        };
+
+        let link = check_tar_entry(&entry, &out_path, select)?;
+        let out_path = target.join(out_path);
+        create_parent(&root, &out_path)?;
+        if entry_type.is_symlink() {
+            check_symlink(&root, &out_path, &entry.link_name()?.unwrap_or_default())?;
+        }
+        match link {
+            Some(link) => {
+                // The links extracted so far could lead it out of the target
+                let source = target.join(&link).canonicalize()?;
+                if !source.starts_with(&root) {
+                    return Err(format!(
+                        "Disallowed hardlink: {} -> {}",
+                        out_path.display(),
+                        link.display()
+                    )
+                    .into());
+                }
+                if out_path.symlink_metadata().is_ok() {
+                    std::fs::remove_file(&out_path)?;
+                }
+                std::fs::hard_link(source, &out_path)?;
+            }
+            None => {
+                entry.unpack(&out_path)?;
+            }
+        }
     }
 
     Ok(ExtractResult::Complete)
 }
+
+/// Safety policy of the tar entries (with their path inside the target directory):
+/// * symlinks must be relative and point inside the target directory
+/// * hardlinks must point to a path inside the target directory
+/// * special files (devices, fifos, ...) are not allowed
+///
+/// Returns the target of hardlinks inside the target directory.
+#[cfg(feature = "ext-tar")]
+fn check_tar_entry<R: Read>(
+    entry: &tar::Entry<R>,
+    path: &Path,
+    select: &Selection,
+) -> Result<Option<PathBuf>, Box<dyn Error>> {
+    let entry_type = entry.header().entry_type();
+    if entry_type.is_file() || entry_type.is_dir() || entry_type.is_gnu_sparse() {
+        return Ok(None);
+    }
+
+    let link = entry.link_name()?.unwrap_or_default();
+    if entry_type.is_symlink() {
+        // Relative to the directory of the symlink
+        let depth = path.components().count();
+        match contained_depth(&link, depth - 1) {
+            Some(_) => Ok(None),
+            None => Err(format!(
+                "Disallowed symlink: {} -> {}",
+                path.display(),
+                link.display()
+            )
+            .into()),
+        }
+    } else if entry_type.is_hard_link() {
+        // Relative to the root of the archive
+        match contained_depth(&link, 0).and_then(|_| select.dir(&link)) {
+            Some(target) => Ok(Some(target)),
+            None => Err(format!(
+                "Disallowed hardlink: {} -> {}",
+                path.display(),
+                link.display()
+            )
+            .into()),
+        }
+    } else {
+        Err(format!("Disallowed entry type {:?}: {}", entry_type, path.display()).into())
+    }
+}
+
+/// Resolves the relative path from a directory `depth` levels deep in the target.
+/// Returns the depth of the result or None if it's absolute or leaves the target.
+#[cfg(feature = "ext-tar")]
+fn contained_depth(path: &Path, mut depth: usize) -> Option<usize> {
+    for component in path.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => {}
+            Component::ParentDir => depth = depth.checked_sub(1)?,
+            Component::RootDir | Component::Prefix(_) => return None,
+        }
+    }
+    Some(depth)
+}
+
+/// Checks that the symlink stays inside the root when it's resolved from its real
+/// parent directory, following the links which were extracted already
+#[cfg(feature = "ext-tar")]
+fn check_symlink(root: &Path, path: &Path, link: &Path) -> Result<(), Box<dyn Error>> {
+    let mut resolved = match path.parent() {
+        Some(parent) => parent.canonicalize()?,
+        None => root.to_path_buf(),
+    };
+
+    for component in link.components() {
+        match component {
+            Component::Normal(part) => {
+                resolved.push(part);
+                if let Ok(real) = resolved.canonicalize() {
+                    resolved = real;
+                }
+            }
+            Component::CurDir => {}
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            Component::RootDir | Component::Prefix(_) => resolved = PathBuf::new(),
+        }
+
+        if !resolved.starts_with(root) {
+            return Err(format!(
+                "Disallowed symlink: {} -> {}",
+                path.display(),
+                link.display()
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Creates the parent directories of the path and checks
+/// that they don't lead out of the root (eg. through symlinks)
+#[cfg(feature = "ext-tar")]
+fn create_parent(root: &Path, path: &Path) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+        if !parent.canonicalize()?.starts_with(root) {
+            return Err(format!("Disallowed path: {}", path.display()).into());
+        }
+    }
+    Ok(())
+}
+
+/// Reports the bytes read from the archive as progress
+#[cfg(feature = "ext-tar")]
+struct ProgressReader<R> {
+    inner: R,
+    progress: Arc<Progress>,
+}
+
+#[cfg(feature = "ext-tar")]
+impl<R: Read> Read for ProgressReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.progress.add_current(len as u64);
+        Ok(len)
+    }
+}
+
+#[cfg(all(test, feature = "ext-tar"))]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    fn tar() -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, content) in [("bin/app", "binary"), ("data.txt", "data")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn target(name: &str) -> PathBuf {
+        let target = std::env::temp_dir().join(name);
+        if target.exists() {
+            std::fs::remove_dir_all(&target).unwrap();
+        }
+        std::fs::create_dir(&target).unwrap();
+        target
+    }
+
+    /// Extracts the compressed tar from a file and checks the result
+    fn extract_file(name: &str, archive: &[u8]) {
+        let target = target(&format!("updater_extract_test_{}", name));
+        let path = target.join(name);
+        std::fs::write(&path, archive).unwrap();
+
+        let progress = Arc::new(Progress::default());
+        let result = asset(name, File::open(&path).unwrap(), &target, progress.clone());
+        assert_eq!(result.unwrap(), ExtractResult::Complete);
+        assert_eq!(
+            std::fs::read_to_string(target.join("bin/app")).unwrap(),
+            "binary"
+        );
+        assert_eq!(progress.maximum(), archive.len() as u64);
+        assert!(progress.current() > 0 && progress.current() <= progress.maximum());
+
+        // Cancelled before the first entry
+        let progress = Arc::new(Progress::default());
+        progress.set_cancelled(true);
+        let result = asset(name, File::open(&path).unwrap(), &target, progress);
+        assert_eq!(result.unwrap(), ExtractResult::Cancelled);
+
+        std::fs::remove_dir_all(target).unwrap();
+    }
+
+    #[cfg(feature = "ext-targz")]
+    fn gzip(tar: &[u8]) -> Vec<u8> {
+        use flate2::{write::GzEncoder, Compression};
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(tar).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[cfg(feature = "ext-targz")]
+    fn targz() -> Vec<u8> {
+        gzip(&tar())
+    }
+
+    /// Creates a tar with empty entries, bypassing the checks of the tar builder
+    #[cfg(feature = "ext-targz")]
+    fn crafted(entries: &[(tar::EntryType, &str, &str)]) -> Vec<u8> {
+        let mut archive = Vec::new();
+        for (entry_type, path, link) in entries {
+            let mut header = tar::Header::new_old();
+            header.set_entry_type(*entry_type);
+            header.set_size(0);
+            header.set_mode(0o644);
+            let raw = header.as_old_mut();
+            raw.name[..path.len()].copy_from_slice(path.as_bytes());
+            raw.linkname[..link.len()].copy_from_slice(link.as_bytes());
+            header.set_cksum();
+            archive.extend_from_slice(header.as_bytes());
+        }
+
+        archive.extend_from_slice(&[0; 1024]);
+        gzip(&archive)
+    }
+
+    #[test]
+    #[cfg(feature = "ext-targz")]
+    fn reject_malicious_entries() {
+        use tar::EntryType;
+
+        let target = target("updater_extract_test_malicious");
+        let dir = target.join("app");
+        std::fs::create_dir(&dir).unwrap();
+
+        for (entry_type, path, link) in [
+            (EntryType::Regular, "../evil", ""),
+            (EntryType::Regular, "/tmp/evil", ""),
+            (EntryType::Regular, "bin/../../evil", ""),
+            (EntryType::Symlink, "evil", "/etc"),
+            (EntryType::Symlink, "evil", "../outside"),
+            (EntryType::Symlink, "bin/evil", "../../outside"),
+            (EntryType::Link, "evil", "../outside"),
+            (EntryType::Link, "evil", "/etc/passwd"),
+            (EntryType::Char, "evil", ""),
+            (EntryType::Block, "evil", ""),
+            (EntryType::Fifo, "evil", ""),
+        ] {
+            let archive = crafted(&[(entry_type, path, link)]);
+            let progress = Arc::new(Progress::default());
+            let result = stream(
+                "app.tar.gz",
+                &archive[..],
+                &dir,
+                progress,
+                &ExtractOptions::default(),
+                |_| true,
+            );
+            let error = result.unwrap_err().to_string();
+            assert!(error.starts_with("Disallowed"), "{}", error);
+        }
+
+        // Nothing got out of the directory
+        let mut entries = std::fs::read_dir(&target).unwrap();
+        assert_eq!(entries.next().unwrap().unwrap().file_name(), "app");
+        assert!(entries.next().is_none());
+        assert!(std::fs::read_dir(&dir).unwrap().next().is_none());
+
+        // Links which are contained on their own, but not when chained
+        #[cfg(unix)]
+        {
+            std::fs::write(target.join("secret.toml"), "secret").unwrap();
+            let archive = crafted(&[
+                (EntryType::Symlink, "d", "."),
+                (EntryType::Symlink, "d/l", ".."),
+                (EntryType::Link, "stolen", "l/secret.toml"),
+            ]);
+            let progress = Arc::new(Progress::default());
+            let result = stream(
+                "app.tar.gz",
+                &archive[..],
+                &dir,
+                progress,
+                &ExtractOptions::default(),
+                |_| true,
+            );
+            let error = result.unwrap_err().to_string();
+            assert!(error.starts_with("Disallowed"), "{}", error);
+            assert!(dir.join("stolen").symlink_metadata().is_err());
+            assert!(dir.join("l").symlink_metadata().is_err());
+        }
+
+        std::fs::remove_dir_all(target).unwrap();
+    }
+
+    #[test]
+    #[cfg(all(unix, feature = "ext-targz"))]
+    fn allow_contained_links() {
+        use tar::EntryType;
+
+        let target = target("updater_extract_test_links");
+        for (entry_type, path, link) in [
+            (EntryType::Symlink, "bin/current", "../lib/app"),
+            (EntryType::Symlink, "latest", "."),
+            (EntryType::Link, "app", "lib/app"),
+        ] {
+            let mut archive = tar::Builder::new(Vec::new());
+            let mut header = tar::Header::new_gnu();
+            header.set_size(3);
+            header.set_mode(0o644);
+            archive
+                .append_data(&mut header, "lib/app", &b"app"[..])
+                .unwrap();
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(entry_type);
+            header.set_size(0);
+            archive.append_link(&mut header, path, link).unwrap();
+
+            let archive = gzip(&archive.into_inner().unwrap());
+            let progress = Arc::new(Progress::default());
+            let result = stream(
+                "app.tar.gz",
+                &archive[..],
+                &target,
+                progress,
+                &ExtractOptions::default(),
+                |_| true,
+            );
+            assert_eq!(result.unwrap(), ExtractResult::Complete);
+            assert!(target.join(path).symlink_metadata().is_ok());
+        }
+
+        std::fs::remove_dir_all(target).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "ext-targz")]
+    fn extract_targz() {
+        extract_file("app.tar.gz", &targz());
+    }
+
+    #[test]
+    #[cfg(feature = "ext-tarxz")]
+    fn extract_tarxz() {
+        let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+        encoder.write_all(&tar()).unwrap();
+        extract_file("app.tar.xz", &encoder.finish().unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "ext-tarzst")]
+    fn extract_tarzst() {
+        let mut encoder = zstd::stream::write::Encoder::new(Vec::new(), 3).unwrap();
+        encoder.write_all(&tar()).unwrap();
+        extract_file("app.tar.zst", &encoder.finish().unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "ext-tarbz2")]
+    fn extract_tarbz2() {
+        use bzip2::{write::BzEncoder, Compression};
+
+        let mut encoder = BzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&tar()).unwrap();
+        extract_file("app.tar.bz2", &encoder.finish().unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "ext-targz")]
+    fn detect_by_content() {
+        // Named without an extension
+        extract_file("app-linux", &targz());
+
+        let extractor = detect("app.tar.xz", &targz()).unwrap();
+        assert_eq!(extractor.name(), "tar.gz");
+        assert!(detect("app-linux", b"not an archive").is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "ext-zip")]
+    fn detect_by_name() {
+        // Formats are still detected by name if the content is unknown (eg. self-extracting)
+        let extractor = detect("app.zip", b"MZ").unwrap();
+        assert_eq!(extractor.name(), "zip");
+    }
+
+    /// A format which stores a single file after its magic bytes
+    struct PackExtractor;
+
+    impl Extractor for PackExtractor {
+        fn name(&self) -> &str {
+            "pack"
+        }
+
+        fn matches_name(&self, name: &str) -> bool {
+            name.ends_with(".pack")
+        }
+
+        fn matches_header(&self, header: &[u8]) -> bool {
+            header.starts_with(b"UPDPACK")
+        }
+
+        fn extract(
+            &self,
+            mut archive: File,
+            target: &Path,
+            _progress: Arc<Progress>,
+            select: &Selection,
+        ) -> Result<ExtractResult, Box<dyn Error>> {
+            let mut content = Vec::new();
+            archive.read_to_end(&mut content)?;
+            if let Some(path) = select.file(Path::new("app")) {
+                std::fs::write(target.join(path), &content[7..])?;
+            }
+            Ok(ExtractResult::Complete)
+        }
+    }
+
+    #[test]
+    fn register_extractor() {
+        register(PackExtractor);
+
+        let target = target("updater_extract_test_register");
+        let path = target.join("app-1.0.0.bin");
+        std::fs::write(&path, "UPDPACKbinary").unwrap();
+
+        let progress = Arc::new(Progress::default());
+        let result = asset(
+            "app-1.0.0.bin",
+            File::open(&path).unwrap(),
+            &target,
+            progress,
+        );
+        assert_eq!(result.unwrap(), ExtractResult::Complete);
+        assert_eq!(
+            std::fs::read_to_string(target.join("app")).unwrap(),
+            "binary"
+        );
+        assert!(!can_stream("app.pack"));
+
+        std::fs::remove_dir_all(target).unwrap();
+    }
+
+    #[test]
+    fn map_paths() {
+        let options = ExtractOptions::default()
+            .with_strip_components(1)
+            .with_subdirectory("./app")
+            .with_include(&["bin/*", "**/*.dll"])
+            .unwrap();
+        let select = Selection::new(&options, &|_| true);
+
+        let file = |path: &str| select.file(Path::new(path));
+        assert_eq!(file("app-1.2.3/app/bin/app").unwrap(), Path::new("bin/app"));
+        assert_eq!(
+            file("./app-1.2.3/app/lib/a/b.dll").unwrap(),
+            Path::new("lib/a/b.dll")
+        );
+        assert_eq!(file("app-1.2.3/app/bin/a/b"), None);
+        assert_eq!(file("app-1.2.3/docs/bin/app"), None);
+        assert_eq!(file("app"), None);
+        assert_eq!(select.dir(Path::new("app-1.2.3/app")), None);
+        assert_eq!(
+            select.dir(Path::new("app-1.2.3/app/lib")).unwrap(),
+            Path::new("lib")
+        );
+    }
+
+    /// Creates a tar.gz with the entries wrapped in a versioned directory
+    #[cfg(feature = "ext-targz")]
+    fn wrapped_targz() -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, content) in [
+            ("app-1.2.3/bin/app", "binary"),
+            ("app-1.2.3/bin/lib.so", "library"),
+            ("app-1.2.3/docs/readme.txt", "readme"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            builder
+                .append_data(&mut header, path, content.as_bytes())
+                .unwrap();
+        }
+        gzip(&builder.into_inner().unwrap())
+    }
+
+    #[test]
+    #[cfg(feature = "ext-targz")]
+    fn strip_components() {
+        let target = target("updater_extract_test_strip");
+        let options = ExtractOptions::default().with_strip_components(1);
+        let progress = Arc::new(Progress::default());
+        let result = stream(
+            "app.tar.gz",
+            &wrapped_targz()[..],
+            &target,
+            progress,
+            &options,
+            |_| true,
+        );
+        assert_eq!(result.unwrap(), ExtractResult::Complete);
+        assert_eq!(
+            std::fs::read_to_string(target.join("bin/app")).unwrap(),
+            "binary"
+        );
+        assert!(target.join("docs/readme.txt").is_file());
+        assert!(!target.join("app-1.2.3").exists());
+
+        std::fs::remove_dir_all(target).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "ext-targz")]
+    fn select_subdirectory() {
+        let target = target("updater_extract_test_subdirectory");
+        let options = ExtractOptions::default()
+            .with_strip_components(1)
+            .with_subdirectory("bin")
+            .with_include(&["*.so"])
+            .unwrap();
+        let progress = Arc::new(Progress::default());
+        let result = stream(
+            "app.tar.gz",
+            &wrapped_targz()[..],
+            &target,
+            progress,
+            &options,
+            |_| true,
+        );
+        assert_eq!(result.unwrap(), ExtractResult::Complete);
+
+        let mut files: Vec<_> = std::fs::read_dir(&target)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["lib.so"]);
+
+        std::fs::remove_dir_all(target).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "ext-targz")]
+    fn check_links_after_strip() {
+        use tar::EntryType;
+
+        // Inside the archive, but not inside the target after stripping
+        let archive = crafted(&[(EntryType::Symlink, "app/evil", "../outside")]);
+        let target = target("updater_extract_test_strip_links");
+        let options = ExtractOptions::default().with_strip_components(1);
+        let progress = Arc::new(Progress::default());
+        let result = stream(
+            "app.tar.gz",
+            &archive[..],
+            &target,
+            progress,
+            &options,
+            |_| true,
+        );
+        assert!(result.unwrap_err().to_string().starts_with("Disallowed"));
+
+        std::fs::remove_dir_all(target).unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "ext-targz")]
+    fn stream_targz() {
+        let target = target("updater_extract_test_stream");
+
+        assert!(can_stream("app.tar.gz"));
+        let progress = Arc::new(Progress::default());
+        let options = ExtractOptions::default();
+        let result = stream(
+            "app.tar.gz",
+            &targz()[..],
+            &target,
+            progress,
+            &options,
+            |path| path != Path::new("data.txt"),
+        );
+        assert_eq!(result.unwrap(), ExtractResult::Complete);
+        assert_eq!(
+            std::fs::read_to_string(target.join("bin/app")).unwrap(),
+            "binary"
+        );
+        assert!(!target.join("data.txt").exists());
+
+        std::fs::remove_dir_all(target).unwrap();
+    }
+}
@@ -1,17 +1,31 @@
-#[cfg(any(feature = "ext-zip", feature = "ext-targz"))]
+#[cfg(feature = "delta")]
+pub mod delta;
+#[cfg(any(
+    feature = "ext-zip",
+    feature = "ext-targz",
+    feature = "ext-tarxz",
+    feature = "ext-tarzst",
+    feature = "ext-tarbz2"
+))]
 pub mod extract;
 #[cfg(feature = "procedures")]
 pub mod procedures;
 pub mod provider;
+pub mod signature;
+pub mod sync;
 #[cfg(feature = "window")]
 pub mod window;
 
+mod channel;
 mod locker;
 mod progress;
+#[cfg(test)]
+mod test_server;
 mod updater;
 mod version;
 
 pub use self::updater::*;
+pub use channel::Channel;
 pub use locker::Locker;
 pub use progress::Progress;
 pub use semver::Version;
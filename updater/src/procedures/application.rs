@@ -1,19 +1,46 @@
-use crate::extract::{self, ExtractResult};
-use crate::provider::{Asset, DownloadResult, Provider};
+#[cfg(feature = "delta")]
+use crate::delta::{self, PatchResult};
+use crate::extract::{self, ExtractOptions, ExtractResult};
+use crate::provider::{to_hex, Asset, ChecksumMismatch, DownloadResult, Provider};
+use crate::signature::{self, AssetSignature, PublicKey, StreamVerifier};
+use crate::sync::{self, FileList};
 use crate::updater::{State, StepAction, StepResult, Updater};
-use log::info;
+use crate::Progress;
+use log::{info, warn};
 use semver::Version;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::error::Error;
 use std::fs::File;
-use std::path::PathBuf;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 pub struct UpdateData {
     pub provider: Box<dyn Provider>,
     pub app_name: String,
     pub asset_name: String,
     pub directory: PathBuf,
+    /// Directory to keep partial downloads in, to resume them on the next run
+    pub download_dir: Option<PathBuf>,
+    /// Key the asset must be signed with. Signatures are not checked without it.
+    pub public_key: Option<PublicKey>,
+    /// Install the asset even if the release has no signature of it
+    pub allow_unsigned: bool,
+    /// Extract tar based assets while downloading them, without a temporary file
+    pub stream_extract: bool,
+    /// Selects what's installed from the asset (eg. strips its top-level directory)
+    pub extract: ExtractOptions,
+    /// Versions which are never installed (eg. because they failed to start)
+    pub blocked_versions: Vec<Version>,
     pub version: Version,
     pub latest: Option<Version>,
     pub asset: Option<Box<dyn Asset>>,
+    /// Patch from the installed version to the latest, used instead of the asset if possible
+    #[cfg(feature = "delta")]
+    pub patch: Option<Box<dyn Asset>>,
+    /// Files of the latest asset with their hashes, to extract only the changed files
+    pub file_list: Option<FileList>,
     pub file: Option<File>,
 }
 
@@ -30,9 +57,18 @@ impl UpdateData {
             app_name,
             asset_name,
             directory,
+            download_dir: None,
+            public_key: None,
+            allow_unsigned: false,
+            stream_extract: false,
+            extract: ExtractOptions::default(),
+            blocked_versions: Vec::new(),
             version,
             latest: None,
             asset: None,
+            #[cfg(feature = "delta")]
+            patch: None,
+            file_list: None,
             file: None,
         }
     }
@@ -42,7 +78,11 @@ pub fn create(data: UpdateData) -> Updater<UpdateData> {
     let mut updater = Updater::new(data);
     updater.set_title(format!("{} Updater", updater.data().app_name));
     updater.add_step(step_check_version);
+    #[cfg(feature = "delta")]
+    updater.add_step(step_patch);
+    updater.add_step(step_stream);
     updater.add_step(step_download);
+    updater.add_step(step_verify);
     updater.add_step(step_install);
     updater
 }
@@ -50,6 +90,8 @@ pub fn create(data: UpdateData) -> Updater<UpdateData> {
 fn step_check_version(state: &mut State, data: &mut UpdateData) -> StepResult {
     state.set_label("Checking for latest version...".into());
 
+    clean_leftovers(&data.directory);
+
     info!("Checking for latest version via {}", data.provider.name());
     data.provider.fetch()?;
 
@@ -60,14 +102,342 @@ fn step_check_version(state: &mut State, data: &mut UpdateData) -> StepResult {
         return Ok(StepAction::Complete);
     }
 
-    data.asset = Some(
-        data.provider
-            .find_asset(data.latest.as_ref().unwrap(), &data.asset_name)?,
-    );
+    if data
+        .blocked_versions
+        .contains(data.latest.as_ref().unwrap())
+    {
+        if !install_path(data, &data.version).is_dir() {
+            return Err(format!(
+                "v{} is blocked and there's no installed version to stay on",
+                data.latest.as_ref().unwrap()
+            )
+            .into());
+        }
+        warn!(
+            "v{} is blocked, staying on v{}",
+            data.latest.as_ref().unwrap(),
+            data.version
+        );
+        data.latest = Some(data.version.clone());
+        return Ok(StepAction::Complete);
+    }
+
+    let latest = data.latest.as_ref().unwrap();
+    let mut asset = data.provider.find_asset(latest, &data.asset_name)?;
+    super::resolve_checksum(data.provider.as_ref(), latest, asset.as_mut())?;
+
+    #[cfg(feature = "delta")]
+    {
+        data.patch = match find_patch(data, asset.name()) {
+            Ok(patch) => patch,
+            Err(e) => {
+                warn!("Failed to look for a patch: {}", e);
+                None
+            }
+        };
+    }
+
+    data.file_list = match fetch_file_list(data, asset.name()) {
+        Ok(list) => list,
+        Err(e) => {
+            warn!("Failed to get the file list: {}", e);
+            None
+        }
+    };
+
+    data.asset = Some(asset);
 
     Ok(StepAction::Continue)
 }
 
+/// Gets the list of the files in the asset if the installed version's files can be reused
+fn fetch_file_list(data: &UpdateData, name: &str) -> Result<Option<FileList>, Box<dyn Error>> {
+    if !install_path(data, &data.version).is_dir() {
+        return Ok(None);
+    }
+
+    let latest = data.latest.as_ref().unwrap();
+    let mut asset = match data.provider.find_file_list(latest, name)? {
+        Some(asset) => asset,
+        None => return Ok(None),
+    };
+    super::resolve_checksum(data.provider.as_ref(), latest, asset.as_mut())?;
+
+    let mut file = match asset.download(Arc::new(Progress::default())) {
+        DownloadResult::Complete(file) => file,
+        DownloadResult::Cancelled => return Ok(None),
+        DownloadResult::Error(e) => return Err(e),
+    };
+
+    if let Some(public_key) = data.public_key.as_ref() {
+        signature::verify_asset(
+            data.provider.as_ref(),
+            latest,
+            asset.name(),
+            &mut file,
+            public_key,
+            data.allow_unsigned,
+        )?;
+    }
+
+    Ok(Some(FileList::from_reader(BufReader::new(file))?))
+}
+
+/// Looks for a patch from the installed version to the latest
+#[cfg(feature = "delta")]
+fn find_patch(data: &UpdateData, name: &str) -> Result<Option<Box<dyn Asset>>, Box<dyn Error>> {
+    if !install_path(data, &data.version).is_dir() {
+        return Ok(None);
+    }
+
+    let latest = data.latest.as_ref().unwrap();
+    let mut patch = match data.provider.find_patch(latest, &data.version, name)? {
+        Some(patch) => patch,
+        None => return Ok(None),
+    };
+    super::resolve_checksum(data.provider.as_ref(), latest, patch.as_mut())?;
+
+    info!("Found patch {}", patch.name());
+    Ok(Some(patch))
+}
+
+/// Updates the installed version with the patch if there is one.
+/// Falls back to the full asset if the patch can't be applied.
+#[cfg(feature = "delta")]
+fn step_patch(state: &mut State, data: &mut UpdateData) -> StepResult {
+    let patch = match data.patch.take() {
+        Some(patch) => patch,
+        None => return Ok(StepAction::Continue),
+    };
+
+    let result = install_staged(data, |staging| {
+        match apply_patch(state, data, patch.as_ref(), staging)? {
+            PatchResult::Complete => Ok(ExtractResult::Complete),
+            PatchResult::Cancelled => Ok(ExtractResult::Cancelled),
+        }
+    });
+
+    match result {
+        Ok(ExtractResult::Complete) => {
+            info!("Patch applied!");
+            Ok(StepAction::Complete)
+        }
+        Ok(ExtractResult::Cancelled) => Ok(StepAction::Cancel),
+        Err(e) => {
+            warn!(
+                "Failed to apply {}, falling back to the full asset: {}",
+                patch.name(),
+                e
+            );
+            Ok(StepAction::Continue)
+        }
+    }
+}
+
+#[cfg(feature = "delta")]
+fn apply_patch(
+    state: &mut State,
+    data: &UpdateData,
+    patch: &dyn Asset,
+    target: &Path,
+) -> Result<PatchResult, Box<dyn Error>> {
+    state.set_label(format!(
+        "Downloading patch {:.2} MB",
+        patch.size() as f64 / 1_000_000.0
+    ));
+
+    let dl_result = match data.download_dir.as_ref() {
+        Some(dir) => patch.download_resumable(dir, state.progress().clone()),
+        None => patch.download(state.progress().clone()),
+    };
+    let mut file = match dl_result {
+        DownloadResult::Complete(file) => file,
+        DownloadResult::Cancelled => return Ok(PatchResult::Cancelled),
+        DownloadResult::Error(e) => return Err(e),
+    };
+
+    if let Some(public_key) = data.public_key.as_ref() {
+        state.set_label("Verifying signature...".into());
+        signature::verify_asset(
+            data.provider.as_ref(),
+            data.latest.as_ref().unwrap(),
+            patch.name(),
+            &mut file,
+            public_key,
+            data.allow_unsigned,
+        )?;
+    }
+
+    state.set_label("Patching...".into());
+    info!(
+        "Patching v{} into v{}",
+        data.version,
+        data.latest.as_ref().unwrap()
+    );
+
+    if target.is_dir() {
+        std::fs::remove_dir_all(target)?;
+    }
+    state.progress().set_current(0);
+    delta::apply(
+        std::io::BufReader::new(file),
+        &install_path(data, &data.version),
+        target,
+        state.progress().clone(),
+    )
+}
+
+/// Downloads and extracts the asset at the same time if enabled and the format allows it
+fn step_stream(state: &mut State, data: &mut UpdateData) -> StepResult {
+    let latest = data.latest.as_ref().unwrap();
+    let name = data.asset.as_ref().unwrap().name();
+    if !data.stream_extract || !extract::can_stream(name) {
+        return Ok(StepAction::Continue);
+    }
+
+    // The signature has to be known before the content
+    let signature = match data.public_key.as_ref() {
+        Some(_) => {
+            AssetSignature::download(data.provider.as_ref(), latest, name, data.allow_unsigned)?
+        }
+        None => None,
+    };
+    let verifier = match (signature.as_ref(), data.public_key.as_ref()) {
+        (Some(signature), Some(public_key)) => match signature.verifier(public_key) {
+            Ok(verifier) => Some(verifier),
+            Err(e) => {
+                warn!("{} can't be verified while streaming: {}", name, e);
+                return Ok(StepAction::Continue);
+            }
+        },
+        _ => None,
+    };
+
+    let result = install_staged(data, |staging| {
+        stream_install(state, data, verifier, staging)
+    });
+
+    match result? {
+        ExtractResult::Complete => Ok(StepAction::Complete),
+        ExtractResult::Cancelled => Ok(StepAction::Cancel),
+    }
+}
+
+fn stream_install(
+    state: &mut State,
+    data: &UpdateData,
+    verifier: Option<StreamVerifier>,
+    target: &Path,
+) -> Result<ExtractResult, Box<dyn Error>> {
+    // Reuse the unchanged files of the installed version
+    let missing = match data.file_list.as_ref() {
+        Some(list) => match link_unchanged(state, data, list, target)? {
+            Some(missing) => Some(missing),
+            None => return Ok(ExtractResult::Cancelled),
+        },
+        None => None,
+    };
+    let linked = match (data.file_list.as_ref(), missing.as_ref()) {
+        (Some(list), Some(missing)) => list.linked(missing),
+        _ => HashSet::new(),
+    };
+
+    let asset = data.asset.as_ref().unwrap();
+    let (reader, size) = asset.open()?;
+    info!(
+        "Downloading and extracting {} - {:.2}MB",
+        asset.name(),
+        size as f64 / 1_000_000.0
+    );
+    state.set_label(format!(
+        "Downloading and installing {:.2} MB",
+        size as f64 / 1_000_000.0
+    ));
+
+    let progress = state.progress().clone();
+    progress.reset();
+    progress.set_maximum(size);
+    progress.set_indeterminate(size == 0);
+
+    let mut reader = StreamReader {
+        reader,
+        progress: progress.clone(),
+        hasher: Sha256::new(),
+        verifier,
+    };
+    let result = extract::stream(
+        asset.name(),
+        &mut reader,
+        target,
+        progress.clone(),
+        &data.extract,
+        |path| !sync::normalize(path).is_some_and(|path| linked.contains(path.as_str())),
+    );
+
+    match result {
+        _ if progress.cancelled() => return Ok(ExtractResult::Cancelled),
+        Ok(ExtractResult::Cancelled) => return Ok(ExtractResult::Cancelled),
+        Ok(ExtractResult::Complete) => {}
+        Err(e) => return Err(format!("Asset download failed: {}", e).into()),
+    }
+
+    // Read what's left after the archive (eg. padding) to verify the whole asset
+    std::io::copy(&mut reader, &mut std::io::sink())?;
+    reader.finish(asset.sha256())?;
+
+    if let (Some(list), Some(missing)) = (data.file_list.as_ref(), missing.as_ref()) {
+        list.verify(target, missing)?;
+    }
+
+    info!("Download and install finished!");
+    Ok(ExtractResult::Complete)
+}
+
+/// Reader of the streamed asset which reports the progress and verifies the content
+struct StreamReader<'a> {
+    reader: Box<dyn Read + Send>,
+    progress: Arc<Progress>,
+    hasher: Sha256,
+    verifier: Option<StreamVerifier<'a>>,
+}
+
+impl StreamReader<'_> {
+    /// Checks the hash and the signature of the content read
+    fn finish(self, sha256: Option<&str>) -> Result<(), Box<dyn Error>> {
+        if let Some(expected) = sha256 {
+            let actual = to_hex(&self.hasher.finalize());
+            if !actual.eq_ignore_ascii_case(expected.trim()) {
+                return Err(ChecksumMismatch {
+                    expected: expected.trim().to_ascii_lowercase(),
+                    actual,
+                }
+                .into());
+            }
+        }
+
+        match self.verifier {
+            Some(verifier) => verifier.finalize(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Read for StreamReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.progress.cancelled() {
+            return Err(std::io::Error::other("Cancelled"));
+        }
+
+        let len = self.reader.read(buf)?;
+        self.hasher.update(&buf[..len]);
+        if let Some(verifier) = self.verifier.as_mut() {
+            verifier.update(&buf[..len]);
+        }
+        self.progress.add_current(len as u64);
+        Ok(len)
+    }
+}
+
 fn step_download(state: &mut State, data: &mut UpdateData) -> StepResult {
     state.set_label(format!(
         "Downloading {:.2} MB",
@@ -80,15 +450,16 @@ fn step_download(state: &mut State, data: &mut UpdateData) -> StepResult {
         data.latest.as_ref().unwrap()
     );
 
-    let dl_result = data
-        .asset
-        .as_ref()
-        .unwrap()
-        .download(state.progress().clone());
+    let asset = data.asset.as_ref().unwrap();
+    let dl_result = match data.download_dir.as_ref() {
+        Some(dir) => asset.download_resumable(dir, state.progress().clone()),
+        None => asset.download(state.progress().clone()),
+    };
 
     let file = match dl_result {
         DownloadResult::Complete(file) => file,
         DownloadResult::Cancelled => return Ok(StepAction::Cancel),
+        DownloadResult::Error(e) if e.is::<ChecksumMismatch>() => return Err(e),
         DownloadResult::Error(e) => return Err(format!("Asset download failed: {}", e).into()),
     };
 
@@ -98,30 +469,333 @@ fn step_download(state: &mut State, data: &mut UpdateData) -> StepResult {
     Ok(StepAction::Continue)
 }
 
+fn step_verify(state: &mut State, data: &mut UpdateData) -> StepResult {
+    let public_key = match data.public_key.as_ref() {
+        Some(key) => key,
+        None => return Ok(StepAction::Continue),
+    };
+
+    state.set_label("Verifying signature...".into());
+
+    signature::verify_asset(
+        data.provider.as_ref(),
+        data.latest.as_ref().unwrap(),
+        data.asset.as_ref().unwrap().name(),
+        data.file.as_mut().unwrap(),
+        public_key,
+        data.allow_unsigned,
+    )?;
+
+    Ok(StepAction::Continue)
+}
+
 fn step_install(state: &mut State, data: &mut UpdateData) -> StepResult {
     state.set_label("Installing...".into());
 
     info!("Starting install");
 
-    // (Re)Create install folder
-    let install_path = data
-        .directory
-        .join(data.latest.as_ref().unwrap().to_string());
-    if install_path.is_dir() {
-        std::fs::remove_dir_all(&install_path)?;
+    // Unpack asset
+    let mut file = data.file.take().unwrap();
+    let result = install_staged(data, |staging| match data.file_list.as_ref() {
+        Some(list) => match install_changed(state, data, list, file.try_clone()?, staging) {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                warn!("Failed to install the changed files only: {}", e);
+
+                // Fall back to extracting everything
+                create_staging(staging)?;
+                file.seek(SeekFrom::Start(0))?;
+                state.progress().reset();
+                extract_asset(state, data, file, staging)
+            }
+        },
+        None => extract_asset(state, data, file, staging),
+    })?;
+
+    if result == ExtractResult::Cancelled {
+        return Ok(StepAction::Cancel);
     }
-    std::fs::create_dir(&install_path)?;
 
-    // Unpack asset
-    if extract::asset(
+    Ok(StepAction::Continue)
+}
+
+fn extract_asset(
+    state: &State,
+    data: &UpdateData,
+    file: File,
+    target: &Path,
+) -> Result<ExtractResult, Box<dyn Error>> {
+    let name = data.asset.as_ref().unwrap().name();
+    let progress = state.progress().clone();
+    extract::asset_filtered(name, file, target, progress, &data.extract, |_| true)
+}
+
+/// Links the unchanged files from the installed version and extracts everything else
+fn install_changed(
+    state: &mut State,
+    data: &UpdateData,
+    list: &FileList,
+    archive: File,
+    target: &Path,
+) -> Result<ExtractResult, Box<dyn Error>> {
+    let missing = match link_unchanged(state, data, list, target)? {
+        Some(missing) => missing,
+        None => return Ok(ExtractResult::Cancelled),
+    };
+
+    let linked = list.linked(&missing);
+
+    state.set_label("Installing...".into());
+    state.progress().reset();
+    let result = extract::asset_filtered(
         data.asset.as_ref().unwrap().name(),
-        data.file.take().unwrap(),
-        &install_path,
+        archive,
+        target,
         state.progress().clone(),
-    )? == ExtractResult::Cancelled
-    {
-        return Ok(StepAction::Cancel);
+        &data.extract,
+        |path| !sync::normalize(path).is_some_and(|path| linked.contains(path.as_str())),
+    )?;
+
+    if result == ExtractResult::Complete {
+        list.verify(target, &missing)?;
     }
+    Ok(result)
+}
 
-    Ok(StepAction::Continue)
+/// Links the unchanged files from the installed version.
+/// Returns the files which still have to be extracted or None if cancelled.
+fn link_unchanged(
+    state: &mut State,
+    data: &UpdateData,
+    list: &FileList,
+    target: &Path,
+) -> Result<Option<HashSet<String>>, Box<dyn Error>> {
+    state.set_label("Copying unchanged files...".into());
+    let old = install_path(data, &data.version);
+    let missing = list.link_unchanged(&old, target, state.progress())?;
+    if state.progress().cancelled() {
+        return Ok(None);
+    }
+
+    info!("{} of {} files changed", missing.len(), list.files.len());
+    Ok(Some(missing))
+}
+
+/// Gets the directory the version is installed in
+fn install_path(data: &UpdateData, version: &Version) -> PathBuf {
+    data.directory.join(version.to_string())
+}
+
+/// Suffix of the directory the version is installed into before it's moved into place
+const STAGING_SUFFIX: &str = ".staging";
+/// Suffix of the replaced installation of the same version while the staged one is moved
+const BACKUP_SUFFIX: &str = ".old";
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Installs the latest version into a staging directory, then moves it into place.
+/// The staging directory is removed if the install fails or gets cancelled,
+/// so the installed versions are never left half-populated.
+fn install_staged<F>(data: &UpdateData, install: F) -> Result<ExtractResult, Box<dyn Error>>
+where
+    F: FnOnce(&Path) -> Result<ExtractResult, Box<dyn Error>>,
+{
+    let target = install_path(data, data.latest.as_ref().unwrap());
+    let staging = with_suffix(&target, STAGING_SUFFIX);
+    create_staging(&staging)?;
+
+    let result = install(&staging).and_then(|result| {
+        if result == ExtractResult::Complete {
+            commit_staging(&staging, &target)?;
+        }
+        Ok(result)
+    });
+
+    if !matches!(result, Ok(ExtractResult::Complete)) && staging.exists() {
+        std::fs::remove_dir_all(&staging)?;
+    }
+    result
+}
+
+/// (Re)Creates the empty staging directory
+fn create_staging(staging: &Path) -> Result<(), Box<dyn Error>> {
+    if staging.exists() {
+        std::fs::remove_dir_all(staging)?;
+    }
+    std::fs::create_dir_all(staging)?;
+    Ok(())
+}
+
+/// Checks the staged version and moves it into place,
+/// replacing a previous installation of the same version
+fn commit_staging(staging: &Path, target: &Path) -> Result<(), Box<dyn Error>> {
+    if std::fs::read_dir(staging)?.next().is_none() {
+        return Err("Nothing was installed from the asset".into());
+    }
+
+    if !target.exists() {
+        std::fs::rename(staging, target)?;
+        return Ok(());
+    }
+
+    let backup = with_suffix(target, BACKUP_SUFFIX);
+    if backup.exists() {
+        std::fs::remove_dir_all(&backup)?;
+    }
+    std::fs::rename(target, &backup)?;
+    if let Err(e) = std::fs::rename(staging, target) {
+        std::fs::rename(&backup, target)?;
+        return Err(e.into());
+    }
+    if let Err(e) = std::fs::remove_dir_all(&backup) {
+        warn!("Failed to remove {}: {}", backup.display(), e);
+    }
+    Ok(())
+}
+
+/// Removes the staging directories of interrupted installs
+/// and restores the installations which were being replaced
+fn clean_leftovers(directory: &Path) {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = match name.to_str() {
+            Some(name) if path.is_dir() => name,
+            _ => continue,
+        };
+
+        let (version, suffix) = match [STAGING_SUFFIX, BACKUP_SUFFIX]
+            .into_iter()
+            .find_map(|suffix| Some((name.strip_suffix(suffix)?, suffix)))
+        {
+            Some((version, suffix)) if Version::parse(version).is_ok() => (version, suffix),
+            _ => continue,
+        };
+
+        let original = directory.join(version);
+        let result = if suffix == STAGING_SUFFIX {
+            info!("Removing the leftover {}", name);
+            std::fs::remove_dir_all(&path)
+        } else if original.exists() {
+            std::fs::remove_dir_all(&path)
+        } else {
+            info!("Restoring v{}", version);
+            std::fs::rename(&path, original)
+        };
+
+        if let Err(e) = result {
+            warn!("Failed to clean up {}: {}", path.display(), e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directory(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir_all(dir.join("1.0.0")).unwrap();
+        std::fs::write(dir.join("1.0.0").join("app"), "1.0.0").unwrap();
+        dir
+    }
+
+    #[test]
+    fn commit_staged_version() {
+        let dir = directory("updater_application_test_commit");
+        let (staging, target) = (dir.join("1.0.0.staging"), dir.join("1.0.0"));
+
+        // Nothing got installed
+        create_staging(&staging).unwrap();
+        assert!(commit_staging(&staging, &target).is_err());
+        assert_eq!(
+            std::fs::read_to_string(target.join("app")).unwrap(),
+            "1.0.0"
+        );
+
+        // Replaces the previous install of the same version
+        std::fs::write(staging.join("app"), "reinstalled").unwrap();
+        commit_staging(&staging, &target).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(target.join("app")).unwrap(),
+            "reinstalled"
+        );
+        assert!(!staging.exists());
+        assert!(!dir.join("1.0.0.old").exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn blocked_latest_version() {
+        use crate::provider::DirectoryProvider;
+
+        let dir = directory("updater_application_test_blocked");
+        let releases = dir.join("releases");
+        std::fs::create_dir(&releases).unwrap();
+        std::fs::write(releases.join("app-1.1.0.zip"), "1.1.0").unwrap();
+
+        let check = |version: Version| {
+            let provider = DirectoryProvider::new(releases.to_str().unwrap());
+            let mut data = UpdateData::new(
+                Box::new(provider),
+                "app".into(),
+                "app.zip".into(),
+                dir.clone(),
+                version,
+            );
+            data.blocked_versions = vec![Version::new(1, 1, 0)];
+            let result = step_check_version(&mut State::default(), &mut data);
+            result.map(|_| data.latest.unwrap())
+        };
+
+        // Stays on the installed version
+        assert_eq!(check(Version::new(1, 0, 0)).unwrap(), Version::new(1, 0, 0));
+        // Nothing to stay on
+        assert!(check(Version::new(0, 0, 0)).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn clean_interrupted_installs() {
+        let dir = directory("updater_application_test_leftovers");
+        for path in [
+            "1.1.0.staging/app",
+            "1.2.0.old/app",
+            "1.0.0.old/app",
+            "cache.old/app",
+        ] {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "leftover").unwrap();
+        }
+
+        clean_leftovers(&dir);
+
+        let mut names: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["1.0.0", "1.2.0", "cache.old"]);
+        assert_eq!(
+            std::fs::read_to_string(dir.join("1.0.0/app")).unwrap(),
+            "1.0.0"
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
 }
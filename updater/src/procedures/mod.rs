@@ -1,4 +1,39 @@
-#[cfg(any(feature = "ext-zip", feature = "ext-targz"))]
+#[cfg(any(
+    feature = "ext-zip",
+    feature = "ext-targz",
+    feature = "ext-tarxz",
+    feature = "ext-tarzst",
+    feature = "ext-tarbz2"
+))]
 pub mod application;
 
 pub mod selfexe;
+
+use crate::provider::{Asset, Provider};
+use log::{info, warn};
+use semver::Version;
+use std::error::Error;
+
+/// Looks up the expected hash of the asset in the checksum file of the release
+/// if the provider doesn't know it.
+pub(crate) fn resolve_checksum(
+    provider: &dyn Provider,
+    version: &Version,
+    asset: &mut dyn Asset,
+) -> Result<(), Box<dyn Error>> {
+    if asset.sha256().is_none() {
+        if let Some(sha256) = provider.checksum(version, asset.name())? {
+            asset.set_sha256(sha256);
+        }
+    }
+
+    match asset.sha256() {
+        Some(sha256) => info!("Expected SHA-256 of {}: {}", asset.name(), sha256),
+        None => warn!(
+            "No checksum found for {}, skipping verification",
+            asset.name()
+        ),
+    }
+
+    Ok(())
+}
@@ -1,4 +1,5 @@
-use crate::provider::{Asset, DownloadResult, Provider};
+use crate::provider::{Asset, ChecksumMismatch, DownloadResult, Provider};
+use crate::signature::{self, PublicKey};
 use crate::updater::{State, StepAction, StepResult, Updater};
 use log::{error, info};
 use semver::Version;
@@ -13,6 +14,8 @@ pub struct UpdateData {
     provider: Box<dyn Provider>,
     version: Version,
     asset_name: String,
+    public_key: Option<PublicKey>,
+    allow_unsigned: bool,
     // Inner state
     self_exe: PathBuf,
     asset: Option<Box<dyn Asset>>,
@@ -25,12 +28,26 @@ impl UpdateData {
             provider,
             version,
             asset_name,
+            public_key: None,
+            allow_unsigned: false,
             self_exe: std::env::current_exe().expect("Failed to get current exe path"),
             asset: None,
             file: None,
         }
     }
 
+    /// Sets the key the new executable must be signed with
+    pub fn with_public_key(mut self, public_key: PublicKey) -> Self {
+        self.public_key = Some(public_key);
+        self
+    }
+
+    /// Allows installing the new executable if its release has no signature of it
+    pub fn with_allow_unsigned(mut self, allow_unsigned: bool) -> Self {
+        self.allow_unsigned = allow_unsigned;
+        self
+    }
+
     fn new_exe(&self) -> PathBuf {
         self.self_exe.with_extension("new")
     }
@@ -46,6 +63,7 @@ pub fn create(data: UpdateData) -> Updater<UpdateData> {
     updater.add_step(step_cleanup);
     updater.add_step(step_check_version);
     updater.add_step(step_download);
+    updater.add_step(step_verify);
     updater.add_step(step_install);
     updater
 }
@@ -74,7 +92,9 @@ fn step_check_version(state: &mut State, data: &mut UpdateData) -> StepResult {
         return Ok(StepAction::Complete);
     }
 
-    data.asset = Some(data.provider.find_asset(&latest, &data.asset_name)?);
+    let mut asset = data.provider.find_asset(&latest, &data.asset_name)?;
+    super::resolve_checksum(data.provider.as_ref(), &latest, asset.as_mut())?;
+    data.asset = Some(asset);
 
     info!("Updating to v{} (from v{})", latest, data.version);
 
@@ -96,6 +116,7 @@ fn step_download(state: &mut State, data: &mut UpdateData) -> StepResult {
     let file = match dl_result {
         DownloadResult::Complete(file) => file,
         DownloadResult::Cancelled => return Ok(StepAction::Cancel),
+        DownloadResult::Error(e) if e.is::<ChecksumMismatch>() => return Err(e),
         DownloadResult::Error(e) => return Err(format!("Asset download failed: {}", e).into()),
     };
 
@@ -105,6 +126,26 @@ fn step_download(state: &mut State, data: &mut UpdateData) -> StepResult {
     Ok(StepAction::Continue)
 }
 
+fn step_verify(state: &mut State, data: &mut UpdateData) -> StepResult {
+    let public_key = match data.public_key.as_ref() {
+        Some(key) => key,
+        None => return Ok(StepAction::Continue),
+    };
+
+    state.set_label("Verifying signature...".into());
+
+    signature::verify_asset(
+        data.provider.as_ref(),
+        &data.version,
+        data.asset.as_ref().unwrap().name(),
+        data.file.as_mut().unwrap(),
+        public_key,
+        data.allow_unsigned,
+    )?;
+
+    Ok(StepAction::Continue)
+}
+
 fn step_install(state: &mut State, data: &mut UpdateData) -> StepResult {
     state.set_label("Installing...".into());
 
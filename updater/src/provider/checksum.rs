@@ -0,0 +1,295 @@
+//! SHA-256 verification of the downloaded assets.
+
+use super::{Asset, DownloadResult};
+use crate::Progress;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+/// Names of the checksum files looked for in a release. `{name}` is the name of the asset.
+const SIDECARS: [&str; 6] = [
+    "{name}.sha256",
+    "{name}.sha256sum",
+    "SHA256SUMS",
+    "SHA256SUMS.txt",
+    "sha256sums.txt",
+    "checksums.txt",
+];
+
+/// Checksum files bigger than this are ignored
+const MAX_SIDECAR_SIZE: u64 = 1_000_000;
+
+/// Error returned when the downloaded content doesn't match the expected hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumMismatch {
+    pub expected: String,
+    pub actual: String,
+}
+
+impl Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "SHA-256 mismatch (expected {}, got {})",
+            self.expected, self.actual
+        )
+    }
+}
+
+impl Error for ChecksumMismatch {}
+
+/// Writer which hashes the data written through it
+pub(crate) struct HashWriter<W: Write> {
+    writer: W,
+    hasher: Sha256,
+}
+
+impl<W: Write> HashWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Adds already written data to the hash
+    pub fn update<R: Read>(&mut self, mut reader: R) -> std::io::Result<()> {
+        std::io::copy(&mut reader, &mut self.hasher)?;
+        Ok(())
+    }
+
+    /// Gets the inner writer without verification
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Compares the hash of the written data to the expected (hex encoded) hash
+    pub fn verify(self, expected: &str) -> Result<W, ChecksumMismatch> {
+        let actual = to_hex(&self.hasher.finalize());
+        match actual.eq_ignore_ascii_case(expected.trim()) {
+            true => Ok(self.writer),
+            false => Err(ChecksumMismatch {
+                expected: expected.trim().to_ascii_lowercase(),
+                actual,
+            }),
+        }
+    }
+}
+
+impl<W: Write> Write for HashWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = self.writer.write(buf)?;
+        self.hasher.update(&buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Gets the hash of the named asset from the checksum file among the assets
+pub(crate) fn from_sidecar(
+    assets: &[&dyn Asset],
+    name: &str,
+) -> Result<Option<String>, Box<dyn Error>> {
+    for template in SIDECARS {
+        let sidecar = template.replace("{name}", name);
+        let asset = match assets.iter().find(|a| a.name() == sidecar) {
+            Some(asset) if asset.size() <= MAX_SIDECAR_SIZE => asset,
+            _ => continue,
+        };
+
+        let mut file = match asset.download(Arc::new(Progress::default())) {
+            DownloadResult::Complete(file) => file,
+            DownloadResult::Cancelled => return Err("Checksum download cancelled".into()),
+            DownloadResult::Error(e) => {
+                return Err(format!("Failed to download {}: {}", sidecar, e).into())
+            }
+        };
+
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+
+        // Only a file of the asset itself may contain just the hash
+        let per_asset = template.contains("{name}");
+        if let Some(hash) = parse(&content, name, per_asset) {
+            return Ok(Some(hash));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Gets the hash of the file from a checksum file.
+///
+/// Supports the GNU ("<hash>  <name>" or "<hash> *<name>") and the BSD ("SHA256 (<name>) = <hash>")
+/// formats, and files containing only the hash if `bare` is allowed.
+fn parse(content: &str, name: &str, bare: bool) -> Option<String> {
+    let is_hash = |s: &str| s.len() == 64 && s.chars().all(|c| c.is_ascii_hexdigit());
+
+    let lines: Vec<&str> = content
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+
+    if let [line] = lines.as_slice() {
+        if bare && is_hash(line) {
+            return Some(line.to_ascii_lowercase());
+        }
+    }
+
+    for line in lines {
+        let (hash, file) = match line.strip_prefix("SHA256 (") {
+            Some(rest) => match rest.rsplit_once(") = ") {
+                Some((file, hash)) => (hash, file),
+                None => continue,
+            },
+            None => match line.split_once(char::is_whitespace) {
+                Some((hash, file)) => (hash, file.trim_start().trim_start_matches('*')),
+                None => continue,
+            },
+        };
+
+        let file = file.trim_start_matches("./");
+        if file == name && is_hash(hash) {
+            return Some(hash.to_ascii_lowercase());
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{GiteaProvider, Provider};
+    use crate::test_server::{Response, TestServer};
+    use semver::Version;
+
+    const EMPTY: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    const HELLO: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    #[test]
+    fn verify_written() {
+        let mut writer = HashWriter::new(Vec::new());
+        writer.write_all(b"hello").unwrap();
+        assert_eq!(writer.verify(&HELLO.to_uppercase()).unwrap(), b"hello");
+
+        let mut writer = HashWriter::new(Vec::new());
+        writer.update(&b"hel"[..]).unwrap();
+        writer.write_all(b"lo").unwrap();
+        assert!(writer.verify(HELLO).is_ok());
+
+        let error = HashWriter::new(Vec::new()).verify(HELLO).unwrap_err();
+        assert_eq!(error.actual, EMPTY);
+        assert_eq!(error.expected, HELLO);
+    }
+
+    fn release(base: &str) -> String {
+        format!(
+            r#"[{{
+                "tag_name": "v1.0.0",
+                "name": "1.0.0",
+                "assets": [
+                    {{ "name": "app.zip", "size": 5, "browser_download_url": "{0}/app.zip" }},
+                    {{ "name": "SHA256SUMS", "size": 76, "browser_download_url": "{0}/SHA256SUMS" }}
+                ]
+            }}]"#,
+            base
+        )
+    }
+
+    fn provider(server: &TestServer) -> GiteaProvider {
+        let mut provider = GiteaProvider::new(&server.base(), "org/app");
+        provider.fetch().unwrap();
+        provider
+    }
+
+    fn server(content: &'static str) -> TestServer {
+        TestServer::new(move |request| {
+            let host = request.header("host").unwrap_or_default();
+            match request.path.as_str() {
+                "/api/v1/repos/org/app/releases" => {
+                    Response::json(release(&format!("http://{}", host)))
+                }
+                "/app.zip" => Response::ok(content),
+                "/SHA256SUMS" => Response::ok(format!("{}  app.zip\n", HELLO)),
+                _ => Response::not_found(),
+            }
+        })
+    }
+
+    #[test]
+    fn verify_download() {
+        let server = server("hello");
+        let provider = provider(&server);
+        let version = Version::new(1, 0, 0);
+
+        assert_eq!(
+            provider.checksum(&version, "app.zip").unwrap().as_deref(),
+            Some(HELLO)
+        );
+        assert_eq!(provider.checksum(&version, "other.zip").unwrap(), None);
+
+        let mut asset = provider.asset(&version, "app.zip").unwrap();
+        asset.set_sha256(HELLO.to_string());
+        assert!(matches!(
+            asset.download(Arc::new(Progress::default())),
+            DownloadResult::Complete(_)
+        ));
+    }
+
+    #[test]
+    fn detect_mismatch() {
+        let server = server("HELLO");
+        let provider = provider(&server);
+        let version = Version::new(1, 0, 0);
+
+        let mut asset = provider.asset(&version, "app.zip").unwrap();
+        asset.set_sha256(provider.checksum(&version, "app.zip").unwrap().unwrap());
+
+        let error = match asset.download(Arc::new(Progress::default())) {
+            DownloadResult::Error(e) => e,
+            result => panic!("Unexpected download result: {:?}", result),
+        };
+        assert_eq!(
+            error.downcast_ref::<ChecksumMismatch>().unwrap().expected,
+            HELLO
+        );
+
+        // The partial file of a resumable download is discarded
+        let dir = std::env::temp_dir().join("updater_test_checksum_resumable");
+        let result = asset.download_resumable(&dir, Arc::new(Progress::default()));
+        assert!(matches!(result, DownloadResult::Error(e) if e.is::<ChecksumMismatch>()));
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn parse_formats() {
+        let gnu = format!("{}  app-linux.tar.gz\n{} *app-win.zip\n", EMPTY, HELLO);
+        assert_eq!(parse(&gnu, "app-win.zip", false).as_deref(), Some(HELLO));
+        assert_eq!(
+            parse(&gnu, "app-linux.tar.gz", false).as_deref(),
+            Some(EMPTY)
+        );
+        assert_eq!(parse(&gnu, "app-mac.zip", false), None);
+
+        let bsd = format!("SHA256 (app-win.zip) = {}", HELLO);
+        assert_eq!(parse(&bsd, "app-win.zip", false).as_deref(), Some(HELLO));
+
+        // Only from the checksum file of the asset
+        let bare = format!("{}\n", HELLO);
+        assert_eq!(parse(&bare, "app.zip", true).as_deref(), Some(HELLO));
+        assert_eq!(parse(&bare, "app.zip", false), None);
+        assert_eq!(parse("not a hash  app-win.zip", "app-win.zip", false), None);
+    }
+}
@@ -0,0 +1,372 @@
+use super::{download_into_tempfile, Asset, DownloadResult, Provider};
+use crate::{version, Channel, Progress};
+use semver::Version;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Provider for a local directory, for offline and air-gapped installs.
+///
+/// The directory is scanned for assets in either of the following layouts:
+/// * `<directory>/<name-with-version>` (eg. "app-win-x64-1.2.0.zip")
+/// * `<directory>/<version>/<name>` (eg. "v1.2.0/app-win-x64.zip")
+#[derive(Debug)]
+pub struct DirectoryProvider {
+    path: PathBuf,
+    channel: Channel,
+    releases: Option<BTreeMap<Version, Vec<DirectoryAsset>>>,
+}
+
+impl DirectoryProvider {
+    /// Creates a new DirectoryProvider.
+    ///
+    /// * `path` is a local path or a `file://` url.
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path_from_url(path),
+            channel: Channel::Stable,
+            releases: None,
+        }
+    }
+
+    /// Gets the scanned data and returns it or Err if not.
+    fn releases(&self) -> Result<&BTreeMap<Version, Vec<DirectoryAsset>>, Box<dyn Error>> {
+        match self.releases.as_ref() {
+            Some(rel) => Ok(rel),
+            None => Err("No fetched content found!".into()),
+        }
+    }
+}
+
+impl Provider for DirectoryProvider {
+    fn name(&self) -> &'static str {
+        "Directory"
+    }
+
+    fn fetch(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut releases: BTreeMap<Version, Vec<DirectoryAsset>> = BTreeMap::new();
+
+        for entry in std::fs::read_dir(&self.path)?.flatten() {
+            let path = entry.path();
+            let version = match file_name(&path) {
+                Some(name) if path.is_dir() => version::extract(name),
+                Some(name) => flat_version(name),
+                None => continue,
+            };
+            let version = match version {
+                Ok(version) => version,
+                Err(_) => continue,
+            };
+
+            let assets = releases.entry(version).or_default();
+            if path.is_dir() {
+                for entry in std::fs::read_dir(&path)?.flatten() {
+                    if let Some(asset) = DirectoryAsset::new(entry.path()) {
+                        assets.push(asset);
+                    }
+                }
+            } else if let Some(asset) = DirectoryAsset::new(path) {
+                assets.push(asset);
+            }
+        }
+
+        self.releases = Some(releases);
+        Ok(())
+    }
+
+    fn set_channel(&mut self, channel: Channel) {
+        self.channel = channel;
+    }
+
+    fn latest(&self) -> Result<Version, Box<dyn Error>> {
+        let releases = self.releases()?;
+
+        let latest = releases
+            .keys()
+            .rev()
+            .find(|version| self.channel.includes(Channel::from_version(version)));
+
+        match latest {
+            Some(version) => Ok(version.clone()),
+            None => Ok(Version::new(0, 0, 0)),
+        }
+    }
+
+    fn assets(&self, version: &Version) -> Result<Vec<&dyn Asset>, Box<dyn Error>> {
+        match self.releases()?.get(version) {
+            Some(assets) => Ok(assets.iter().map(|x| x as &dyn Asset).collect()),
+            None => Err("Version not found".into()),
+        }
+    }
+}
+
+/// Converts a `file://` url into a path. Anything else is treated as a path.
+fn path_from_url(url: &str) -> PathBuf {
+    match url.strip_prefix("file://") {
+        // "file:///C:/dir" on Windows
+        Some(path) if cfg!(windows) && path.starts_with('/') && path.get(2..3) == Some(":") => {
+            PathBuf::from(&path[1..])
+        }
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from(url),
+    }
+}
+
+/// Gets the version of a file in the flat layout. Patches start with the version they
+/// update from (see `patch_name`), so the version of the patched asset is used for them.
+fn flat_version(name: &str) -> Result<Version, Box<dyn Error>> {
+    let version = version::extract(name)?;
+    if !name.ends_with(".patch") {
+        return Ok(version);
+    }
+
+    match name.strip_prefix(&format!("{}-", version)) {
+        Some(asset) => version::extract(asset),
+        None => Ok(version),
+    }
+}
+
+fn file_name(path: &Path) -> Option<&str> {
+    path.file_name().and_then(|name| name.to_str())
+}
+
+#[derive(Debug, Clone)]
+struct DirectoryAsset {
+    name: String,
+    size: u64,
+    url: String,
+    path: PathBuf,
+    sha256: Option<String>,
+}
+
+impl DirectoryAsset {
+    /// Creates the asset if the path points to a file
+    fn new(path: PathBuf) -> Option<Self> {
+        let metadata = path.metadata().ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+
+        Some(Self {
+            name: file_name(&path)?.to_string(),
+            size: metadata.len(),
+            url: format!("file://{}", path.display()),
+            path,
+            sha256: None,
+        })
+    }
+}
+
+impl Asset for DirectoryAsset {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn sha256(&self) -> Option<&str> {
+        self.sha256.as_deref()
+    }
+
+    fn set_sha256(&mut self, sha256: String) {
+        self.sha256 = Some(sha256);
+    }
+
+    fn box_clone(&self) -> Box<dyn Asset> {
+        Box::new(self.clone())
+    }
+
+    fn open(&self) -> Result<(Box<dyn Read + Send>, u64), Box<dyn Error>> {
+        Ok((Box::new(File::open(&self.path)?), self.size()))
+    }
+
+    /// Copy the asset into a temporary file
+    fn download(&self, progress: Arc<Progress>) -> DownloadResult {
+        log::info!(
+            "Copying {} - {:.2}MB",
+            self.name(),
+            self.size() as f64 / 1_000_000.0
+        );
+
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) => return DownloadResult::Error(e.into()),
+        };
+
+        // Setup progress
+        progress.set_maximum(self.size());
+        progress.set_indeterminate(false);
+
+        download_into_tempfile(file, progress, self.sha256())
+    }
+
+    /// Local files are copied without a partial file
+    fn download_resumable(&self, _dir: &Path, progress: Arc<Progress>) -> DownloadResult {
+        self.download(progress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directory(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        std::fs::create_dir_all(dir.join("v1.1.0")).unwrap();
+        std::fs::write(dir.join("app-win-x64-1.0.0.zip"), "1.0.0").unwrap();
+        std::fs::write(dir.join("v1.1.0").join("app-win-x64.zip"), "1.1.0").unwrap();
+        std::fs::write(dir.join("readme.txt"), "no version").unwrap();
+        std::fs::write(dir.join("app-win-x64-1.2.0-beta.zip"), "1.2.0-beta").unwrap();
+        dir
+    }
+
+    #[test]
+    fn scan_directory() {
+        let dir = directory("updater_test_directory_scan");
+        let mut provider = DirectoryProvider::new(dir.to_str().unwrap());
+        provider.fetch().unwrap();
+
+        assert_eq!(provider.latest().unwrap(), Version::new(1, 1, 0));
+        provider.set_channel(Channel::Beta);
+        assert_eq!(
+            provider.latest().unwrap(),
+            Version::parse("1.2.0-beta").unwrap()
+        );
+
+        let asset = provider
+            .find_asset(&Version::new(1, 0, 0), "app-win-x64")
+            .unwrap();
+        assert_eq!(asset.name(), "app-win-x64-1.0.0.zip");
+        assert_eq!(asset.size(), 5);
+
+        let asset = provider
+            .asset(&Version::new(1, 1, 0), "app-win-x64.zip")
+            .unwrap();
+        assert!(asset.url().starts_with("file://"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn copy_asset() {
+        let dir = directory("updater_test_directory_copy");
+        let mut provider = DirectoryProvider::new(&format!("file://{}", dir.display()));
+        provider.fetch().unwrap();
+
+        let asset = provider
+            .find_asset(&Version::new(1, 1, 0), "app-win-x64")
+            .unwrap();
+        let progress = Arc::new(Progress::default());
+        let mut file = match asset.download(progress.clone()) {
+            DownloadResult::Complete(file) => file,
+            result => panic!("Unexpected download result: {:?}", result),
+        };
+
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "1.1.0");
+        assert_eq!(progress.current(), 5);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn copy_cancelled() {
+        let dir = directory("updater_test_directory_cancel");
+        let mut provider = DirectoryProvider::new(dir.to_str().unwrap());
+        provider.fetch().unwrap();
+
+        let asset = provider
+            .find_asset(&Version::new(1, 1, 0), "app-win-x64")
+            .unwrap();
+        let progress = Arc::new(Progress::default());
+        progress.set_cancelled(true);
+        assert!(matches!(
+            asset.download(progress),
+            DownloadResult::Cancelled
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn find_patch() {
+        let dir = directory("updater_test_directory_patch");
+        let patch_path = dir.join("v1.1.0").join("1.0.0-app-win-x64.zip.patch");
+        std::fs::write(patch_path, "patch").unwrap();
+        let mut provider = DirectoryProvider::new(dir.to_str().unwrap());
+        provider.fetch().unwrap();
+
+        let (from, to) = (Version::new(1, 0, 0), Version::new(1, 1, 0));
+        let patch = provider.find_patch(&to, &from, "app-win-x64.zip").unwrap();
+        assert_eq!(patch.unwrap().name(), "1.0.0-app-win-x64.zip.patch");
+        assert!(provider
+            .find_patch(&to, &Version::new(0, 9, 0), "app-win-x64.zip")
+            .unwrap()
+            .is_none());
+
+        // The patch isn't mistaken for the asset
+        let asset = provider.find_asset(&to, "app-win-x64").unwrap();
+        assert_eq!(asset.name(), "app-win-x64.zip");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn find_flat_patch() {
+        let dir = directory("updater_test_directory_flat_patch");
+        let patch_name = "1.0.0-app-win-x64-1.2.0-beta.zip.patch";
+        std::fs::write(dir.join(patch_name), "patch").unwrap();
+        let mut provider = DirectoryProvider::new(dir.to_str().unwrap());
+        provider.fetch().unwrap();
+
+        // Filed under the version it updates to
+        let (from, to) = (Version::new(1, 0, 0), Version::parse("1.2.0-beta").unwrap());
+        let patch = provider
+            .find_patch(&to, &from, "app-win-x64-1.2.0-beta.zip")
+            .unwrap();
+        assert_eq!(patch.unwrap().name(), patch_name);
+        assert_eq!(provider.assets(&from).unwrap().len(), 1);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn skip_sidecars() {
+        let dir = directory("updater_test_directory_sidecars");
+        for sidecar in ["app-win-x64.zip.files.json", "app-win-x64.zip.minisig"] {
+            std::fs::write(dir.join("v1.1.0").join(sidecar), "sidecar").unwrap();
+        }
+        let mut provider = DirectoryProvider::new(dir.to_str().unwrap());
+        provider.fetch().unwrap();
+
+        let version = Version::new(1, 1, 0);
+        let asset = provider.find_asset(&version, "app-win-x64").unwrap();
+        assert_eq!(asset.name(), "app-win-x64.zip");
+
+        let list = provider
+            .find_file_list(&version, "app-win-x64.zip")
+            .unwrap();
+        assert_eq!(list.unwrap().name(), "app-win-x64.zip.files.json");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_directory() {
+        let mut provider = DirectoryProvider::new("/this/directory/does/not/exist");
+        assert!(provider.fetch().is_err());
+    }
+}
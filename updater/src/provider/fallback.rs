@@ -0,0 +1,330 @@
+use super::{Asset, DownloadResult, Provider, ResponseCache};
+use crate::{Channel, Progress};
+use log::warn;
+use semver::Version;
+use std::error::Error;
+use std::io::Read;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Composite provider which uses the first of its providers that fetches successfully.
+pub struct FallbackProvider {
+    providers: Vec<Box<dyn Provider>>,
+    mirrors: Vec<String>,
+    active: Option<usize>,
+}
+
+impl FallbackProvider {
+    /// Creates a new FallbackProvider.
+    ///
+    /// * `providers` are tried in order.
+    pub fn new(providers: Vec<Box<dyn Provider>>) -> Self {
+        Self {
+            providers,
+            mirrors: Vec::new(),
+            active: None,
+        }
+    }
+
+    /// Sets the mirror urls to download assets from if the original url fails.
+    ///
+    /// * `mirrors` are url templates where "{version}" and "{name}" are replaced with the
+    ///   version and the name of the asset.
+    pub fn with_mirrors(mut self, mirrors: Vec<String>) -> Self {
+        self.mirrors = mirrors;
+        self
+    }
+
+    /// Gets the provider which fetched successfully or Err if none.
+    fn active(&self) -> Result<&dyn Provider, Box<dyn Error>> {
+        match self.active {
+            Some(index) => Ok(self.providers[index].as_ref()),
+            None => Err("No fetched content found!".into()),
+        }
+    }
+
+    /// Wraps the asset so its download falls back to the mirrors
+    fn with_fallback(&self, version: &Version, asset: Box<dyn Asset>) -> Box<dyn Asset> {
+        if self.mirrors.is_empty() {
+            return asset;
+        }
+
+        let mut assets = vec![asset];
+        for mirror in &self.mirrors {
+            let url = mirror
+                .replace("{version}", &version.to_string())
+                .replace("{name}", assets[0].name());
+            assets.push(Box::new(MirrorAsset {
+                name: assets[0].name().to_string(),
+                size: assets[0].size(),
+                url,
+                sha256: assets[0].sha256().map(String::from),
+            }));
+        }
+
+        Box::new(FallbackAsset { assets })
+    }
+}
+
+impl Provider for FallbackProvider {
+    fn name(&self) -> &'static str {
+        match self.active() {
+            Ok(provider) => provider.name(),
+            Err(_) => "Fallback",
+        }
+    }
+
+    fn fetch(&mut self) -> Result<(), Box<dyn Error>> {
+        self.active = None;
+
+        let mut errors = Vec::new();
+        for (index, provider) in self.providers.iter_mut().enumerate() {
+            match provider.fetch() {
+                Ok(()) => {
+                    self.active = Some(index);
+                    return Ok(());
+                }
+                Err(e) => {
+                    warn!("Failed to fetch from {}: {}", provider.name(), e);
+                    errors.push(format!("{}: {}", provider.name(), e));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Err("No provider was specified!".into())
+        } else {
+            Err(format!("All providers failed ({})", errors.join("; ")).into())
+        }
+    }
+
+    fn set_channel(&mut self, channel: Channel) {
+        for provider in self.providers.iter_mut() {
+            provider.set_channel(channel);
+        }
+    }
+
+    fn set_cache(&mut self, cache: ResponseCache) {
+        for provider in self.providers.iter_mut() {
+            provider.set_cache(cache.clone());
+        }
+    }
+
+    fn latest(&self) -> Result<Version, Box<dyn Error>> {
+        self.active()?.latest()
+    }
+
+    fn assets(&self, version: &Version) -> Result<Vec<&dyn Asset>, Box<dyn Error>> {
+        self.active()?.assets(version)
+    }
+
+    fn asset(&self, version: &Version, name: &str) -> Result<Box<dyn Asset>, Box<dyn Error>> {
+        let asset = self.active()?.asset(version, name)?;
+        Ok(self.with_fallback(version, asset))
+    }
+
+    fn find_asset(&self, version: &Version, name: &str) -> Result<Box<dyn Asset>, Box<dyn Error>> {
+        let asset = self.active()?.find_asset(version, name)?;
+        Ok(self.with_fallback(version, asset))
+    }
+}
+
+/// Same asset on a mirror
+#[derive(Debug, Clone)]
+struct MirrorAsset {
+    name: String,
+    size: u64,
+    url: String,
+    sha256: Option<String>,
+}
+
+impl Asset for MirrorAsset {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn sha256(&self) -> Option<&str> {
+        self.sha256.as_deref()
+    }
+
+    fn set_sha256(&mut self, sha256: String) {
+        self.sha256 = Some(sha256);
+    }
+
+    fn box_clone(&self) -> Box<dyn Asset> {
+        Box::new(self.clone())
+    }
+}
+
+/// Asset which tries to download from its alternatives in order
+struct FallbackAsset {
+    assets: Vec<Box<dyn Asset>>,
+}
+
+impl Asset for FallbackAsset {
+    fn name(&self) -> &str {
+        self.assets[0].name()
+    }
+
+    fn size(&self) -> u64 {
+        self.assets[0].size()
+    }
+
+    fn url(&self) -> &str {
+        self.assets[0].url()
+    }
+
+    fn sha256(&self) -> Option<&str> {
+        self.assets[0].sha256()
+    }
+
+    fn set_sha256(&mut self, sha256: String) {
+        for asset in self.assets.iter_mut() {
+            asset.set_sha256(sha256.clone());
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn Asset> {
+        Box::new(FallbackAsset {
+            assets: self.assets.iter().map(|a| a.box_clone()).collect(),
+        })
+    }
+
+    fn open(&self) -> Result<(Box<dyn Read + Send>, u64), Box<dyn Error>> {
+        let mut last_error = None;
+
+        for asset in &self.assets {
+            match asset.open() {
+                Ok(opened) => return Ok(opened),
+                Err(e) => {
+                    warn!("Opening {} failed: {}", asset.url(), e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| "No asset to open".into()))
+    }
+
+    fn download(&self, progress: Arc<Progress>) -> DownloadResult {
+        self.download_with(&progress, |asset| asset.download(progress.clone()))
+    }
+
+    fn download_resumable(&self, dir: &Path, progress: Arc<Progress>) -> DownloadResult {
+        self.download_with(&progress, |asset| {
+            asset.download_resumable(dir, progress.clone())
+        })
+    }
+}
+
+impl FallbackAsset {
+    /// Downloads with the function from the first asset which succeeds
+    fn download_with<F>(&self, progress: &Progress, download: F) -> DownloadResult
+    where
+        F: Fn(&dyn Asset) -> DownloadResult,
+    {
+        let mut last_error = None;
+
+        for asset in &self.assets {
+            match download(asset.as_ref()) {
+                DownloadResult::Error(e) => {
+                    warn!("Download from {} failed: {}", asset.url(), e);
+                    progress.set_current(0);
+                    last_error = Some(e);
+                }
+                result => return result,
+            }
+        }
+
+        DownloadResult::Error(last_error.unwrap_or_else(|| "No asset to download".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::{GiteaProvider, ManifestProvider};
+    use crate::test_server::{Response, TestServer};
+
+    const MANIFEST: &str = r#"{
+        "releases": [{
+            "version": "1.4.0",
+            "assets": [{ "name": "app.zip", "size": 6, "url": "/missing/app.zip" }]
+        }]
+    }"#;
+
+    fn server() -> TestServer {
+        TestServer::with_routes(vec![
+            ("/manifest.json", Response::json(MANIFEST)),
+            ("/mirror/1.4.0/app.zip", Response::ok("mirror")),
+        ])
+    }
+
+    fn provider(server: &TestServer) -> FallbackProvider {
+        FallbackProvider::new(vec![
+            Box::new(GiteaProvider::new(&server.base(), "org/missing")),
+            Box::new(ManifestProvider::new(&server.url("/manifest.json"))),
+        ])
+    }
+
+    #[test]
+    fn first_successful_provider() {
+        let server = server();
+        let mut provider = provider(&server);
+        assert!(provider.latest().is_err());
+
+        provider.fetch().unwrap();
+        assert_eq!(provider.name(), "Manifest");
+        assert_eq!(provider.latest().unwrap(), Version::new(1, 4, 0));
+    }
+
+    #[test]
+    fn all_providers_fail() {
+        let server = TestServer::with_routes(vec![]);
+        let mut provider = provider(&server);
+        assert!(provider.fetch().is_err());
+        assert!(provider.latest().is_err());
+
+        let mut provider = FallbackProvider::new(Vec::new());
+        assert!(provider.fetch().is_err());
+    }
+
+    #[test]
+    fn download_from_mirror() {
+        let server = server();
+        let mut provider =
+            provider(&server).with_mirrors(vec![server.url("/mirror/{version}/{name}")]);
+        provider.fetch().unwrap();
+
+        let asset = provider.find_asset(&Version::new(1, 4, 0), "app").unwrap();
+        let progress = Arc::new(Progress::default());
+        let mut file = match asset.download(progress.clone()) {
+            DownloadResult::Complete(file) => file,
+            result => panic!("Unexpected download result: {:?}", result),
+        };
+
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "mirror");
+        assert_eq!(progress.current(), 6);
+    }
+
+    #[test]
+    fn download_without_mirror() {
+        let server = server();
+        let mut provider = provider(&server);
+        provider.fetch().unwrap();
+
+        let asset = provider.find_asset(&Version::new(1, 4, 0), "app").unwrap();
+        let progress = Arc::new(Progress::default());
+        assert!(matches!(asset.download(progress), DownloadResult::Error(_)));
+    }
+}
@@ -0,0 +1,275 @@
+use super::{http, Asset, Provider, ResponseCache};
+use crate::{version, Channel};
+use semver::Version;
+use serde::Deserialize;
+use std::error::Error;
+
+/// Provider for Gitea and Forgejo instances.
+#[derive(Debug)]
+pub struct GiteaProvider {
+    url: String,
+    channel: Channel,
+    cache: Option<ResponseCache>,
+    releases: Option<Vec<GiteaRelease>>,
+}
+
+impl GiteaProvider {
+    /// Creates a new GiteaProvider.
+    ///
+    /// * `base_url` is the url of the instance (eg. "https://codeberg.org").
+    /// * `repo` should be "*owner*/*repository*".
+    pub fn new(base_url: &str, repo: &str) -> Self {
+        Self {
+            url: format!(
+                "{}/api/v1/repos/{}/releases",
+                base_url.trim_end_matches('/'),
+                repo
+            ),
+            channel: Channel::Stable,
+            cache: None,
+            releases: None,
+        }
+    }
+
+    /// Gets the fetched data and returns it or Err if not.
+    fn releases(&self) -> Result<&Vec<GiteaRelease>, Box<dyn Error>> {
+        match self.releases.as_ref() {
+            Some(rel) => Ok(rel),
+            None => Err("No fetched content found!".into()),
+        }
+    }
+}
+
+impl Provider for GiteaProvider {
+    fn name(&self) -> &'static str {
+        "Gitea"
+    }
+
+    fn fetch(&mut self) -> Result<(), Box<dyn Error>> {
+        let request = http::agent()
+            .get(&self.url)
+            .set("Accept", "application/json");
+        let response = http::get(request, self.cache.as_ref())?;
+
+        let release: GiteaResponse = json::from_str(&response.body)?;
+
+        match release {
+            GiteaResponse::Release(release) => {
+                self.releases = Some(release);
+                Ok(())
+            }
+            GiteaResponse::Error(err) => Err(err.message.into()),
+        }
+    }
+
+    fn set_channel(&mut self, channel: Channel) {
+        self.channel = channel;
+    }
+
+    fn set_cache(&mut self, cache: ResponseCache) {
+        self.cache = Some(cache);
+    }
+
+    fn latest(&self) -> Result<Version, Box<dyn Error>> {
+        let releases = self.releases()?;
+
+        let mut latest_version = Version::new(0, 0, 0);
+
+        // Gets the version from the release tag
+        for release in releases.iter().filter(|r| !r.draft) {
+            let version = release.version()?;
+            if !self.channel.includes(release.channel(&version)) {
+                continue;
+            }
+            if version > latest_version {
+                latest_version = version;
+            }
+        }
+
+        Ok(latest_version)
+    }
+
+    fn assets(&self, version: &Version) -> Result<Vec<&dyn Asset>, Box<dyn Error>> {
+        let releases = self.releases()?;
+
+        for release in releases {
+            if release.version()? == *version {
+                return Ok(release.assets.iter().map(|x| x as &dyn Asset).collect());
+            }
+        }
+
+        Err("Version not found".into())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum GiteaResponse {
+    Release(Vec<GiteaRelease>),
+    Error(GiteaError),
+}
+
+#[derive(Debug, Deserialize)]
+struct GiteaError {
+    message: String,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+struct GiteaRelease {
+    name: String,
+    tag_name: String,
+    #[serde(default)]
+    draft: bool,
+    #[serde(default)]
+    prerelease: bool,
+    assets: Vec<GiteaAsset>,
+}
+
+impl GiteaRelease {
+    pub fn version(&self) -> Result<Version, Box<dyn Error>> {
+        version::extract(&self.tag_name)
+    }
+
+    /// Gets the channel from the version or from the prerelease flag
+    pub fn channel(&self, version: &Version) -> Channel {
+        match self.prerelease {
+            true => Channel::from_version(version).max(Channel::Beta),
+            false => Channel::from_version(version),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct GiteaAsset {
+    name: String,
+    size: u64,
+    browser_download_url: String,
+    #[serde(skip)]
+    sha256: Option<String>,
+}
+
+impl Asset for GiteaAsset {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn url(&self) -> &str {
+        &self.browser_download_url
+    }
+
+    fn sha256(&self) -> Option<&str> {
+        self.sha256.as_deref()
+    }
+
+    fn set_sha256(&mut self, sha256: String) {
+        self.sha256 = Some(sha256);
+    }
+
+    fn box_clone(&self) -> Box<dyn Asset> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Response, TestServer};
+
+    const RELEASES: &str = r#"[
+        {
+            "id": 4,
+            "tag_name": "v1.2.1",
+            "name": "Testing",
+            "draft": false,
+            "prerelease": true,
+            "assets": []
+        },
+        {
+            "id": 3,
+            "tag_name": "v1.3.0",
+            "name": "Unpublished",
+            "draft": true,
+            "prerelease": false,
+            "assets": []
+        },
+        {
+            "id": 2,
+            "tag_name": "v1.2.0",
+            "name": "1.2.0",
+            "draft": false,
+            "prerelease": false,
+            "assets": [
+                {
+                    "id": 7,
+                    "name": "app-win-x64.zip",
+                    "size": 1024,
+                    "download_count": 3,
+                    "browser_download_url": "https://forgejo.example.com/org/app/releases/download/v1.2.0/app-win-x64.zip"
+                }
+            ]
+        },
+        {
+            "id": 1,
+            "tag_name": "v1.1.0",
+            "name": "1.1.0",
+            "draft": false,
+            "prerelease": false,
+            "assets": []
+        }
+    ]"#;
+
+    fn fetched(server: &TestServer) -> GiteaProvider {
+        let mut provider = GiteaProvider::new(&server.base(), "org/app");
+        provider.fetch().unwrap();
+        provider
+    }
+
+    #[test]
+    fn latest_skips_drafts() {
+        let server = TestServer::with_routes(vec![(
+            "/api/v1/repos/org/app/releases",
+            Response::json(RELEASES),
+        )]);
+        let mut provider = fetched(&server);
+        assert_eq!(provider.latest().unwrap(), Version::new(1, 2, 0));
+
+        provider.set_channel(Channel::Beta);
+        assert_eq!(provider.latest().unwrap(), Version::new(1, 2, 1));
+    }
+
+    #[test]
+    fn find_asset() {
+        let server = TestServer::with_routes(vec![(
+            "/api/v1/repos/org/app/releases",
+            Response::json(RELEASES),
+        )]);
+        let provider = fetched(&server);
+
+        let asset = provider
+            .find_asset(&Version::new(1, 2, 0), "app-win-x64")
+            .unwrap();
+        assert_eq!(asset.name(), "app-win-x64.zip");
+        assert_eq!(asset.size(), 1024);
+        assert_eq!(
+            asset.url(),
+            "https://forgejo.example.com/org/app/releases/download/v1.2.0/app-win-x64.zip"
+        );
+
+        assert!(provider
+            .find_asset(&Version::new(1, 1, 0), "app-win-x64")
+            .is_err());
+    }
+
+    #[test]
+    fn fetch_error() {
+        let server = TestServer::with_routes(vec![]);
+        let mut provider = GiteaProvider::new(&server.base(), "org/app");
+        assert!(provider.fetch().is_err());
+        assert!(provider.latest().is_err());
+    }
+}
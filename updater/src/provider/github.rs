@@ -1,13 +1,21 @@
-use super::{Asset, Provider};
-use crate::version;
+use super::{http, Asset, Provider, ResponseCache};
+use crate::{version, Channel};
+use log::warn;
 use semver::Version;
 use serde::Deserialize;
 use std::error::Error;
-use std::time::Duration;
+
+/// Number of releases requested per page (maximum allowed by GitHub)
+const PER_PAGE: u32 = 100;
+/// Maximum number of pages to fetch
+const MAX_PAGES: usize = 10;
 
 #[derive(Debug)]
 pub struct GitHubProvider {
     url: String,
+    token: Option<String>,
+    channel: Channel,
+    cache: Option<ResponseCache>,
     releases: Option<Vec<GitHubRelease>>,
 }
 
@@ -16,12 +24,40 @@ impl GitHubProvider {
     ///
     /// * `repo` should be "*user*/*repository*".
     pub fn new(repo: &str) -> Self {
+        Self::with_api_url("https://api.github.com", repo)
+    }
+
+    /// Creates a new GitHubProvider for a GitHub Enterprise Server.
+    ///
+    /// * `api_url` is the url of the REST API (eg. "https://github.example.com/api/v3").
+    /// * `repo` should be "*user*/*repository*".
+    pub fn with_api_url(api_url: &str, repo: &str) -> Self {
         Self {
-            url: format!("https://api.github.com/repos/{}/releases", repo),
+            url: format!("{}/repos/{}/releases", api_url.trim_end_matches('/'), repo),
+            token: None,
+            channel: Channel::Stable,
+            cache: None,
             releases: None,
         }
     }
 
+    /// Sets the access token used for the requests.
+    /// Needed for private repositories and for a higher rate limit.
+    pub fn with_token(mut self, token: String) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// Sets whether prereleases are considered when looking for the latest version.
+    /// Same as selecting the `Beta` (or `Stable`) channel.
+    pub fn with_prereleases(mut self, prereleases: bool) -> Self {
+        self.channel = match prereleases {
+            true => Channel::Beta,
+            false => Channel::Stable,
+        };
+        self
+    }
+
     /// Gets the fetched data and returns it or Err if not.
     fn releases(&self) -> Result<&Vec<GitHubRelease>, Box<dyn Error>> {
         match self.releases.as_ref() {
@@ -29,6 +65,32 @@ impl GitHubProvider {
             None => Err("No fetched content found!".into()),
         }
     }
+
+    /// Fetches a single page of releases. Returns the url of the next page if any.
+    fn fetch_page(
+        &self,
+        url: &str,
+        releases: &mut Vec<GitHubRelease>,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        let mut request = http::agent()
+            .get(url)
+            .set("Accept", "application/vnd.github.v3+json");
+        if let Some(token) = self.token.as_ref() {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+        let response = http::get(request, self.cache.as_ref())?;
+
+        let next = response.link.as_deref().and_then(next_page);
+        let release: GitHubResponse = json::from_str(&response.body)?;
+
+        match release {
+            GitHubResponse::Release(mut release) => {
+                releases.append(&mut release);
+                Ok(next)
+            }
+            GitHubResponse::Error(err) => Err(err.message.into()),
+        }
+    }
 }
 
 impl Provider for GitHubProvider {
@@ -37,22 +99,38 @@ impl Provider for GitHubProvider {
     }
 
     fn fetch(&mut self) -> Result<(), Box<dyn Error>> {
-        let response = ureq::get(&self.url)
-            .set("Accept", "application/vnd.github.v3+json")
-            .timeout(Duration::from_secs(10))
-            .call()?;
+        let mut releases = Vec::new();
+        let mut url = Some(format!("{}?per_page={}", self.url, PER_PAGE));
 
-        // TODO: Handle timeouts nicely
+        for _ in 0..MAX_PAGES {
+            url = match url {
+                Some(url) => self.fetch_page(&url, &mut releases)?,
+                None => break,
+            };
+        }
 
-        let release: GitHubResponse = json::from_reader(response.into_reader())?;
+        if url.is_some() {
+            warn!(
+                "Too many releases, only the first {} were fetched",
+                releases.len()
+            );
+        }
 
-        match release {
-            GitHubResponse::Release(release) => {
-                self.releases = Some(release);
-                Ok(())
-            }
-            GitHubResponse::Error(err) => Err(err.message.into()),
+        // Assets of private repositories can only be downloaded with the token
+        for asset in releases.iter_mut().flat_map(|r| r.assets.iter_mut()) {
+            asset.token = self.token.clone();
         }
+
+        self.releases = Some(releases);
+        Ok(())
+    }
+
+    fn set_channel(&mut self, channel: Channel) {
+        self.channel = channel;
+    }
+
+    fn set_cache(&mut self, cache: ResponseCache) {
+        self.cache = Some(cache);
     }
 
     fn latest(&self) -> Result<Version, Box<dyn Error>> {
@@ -61,8 +139,11 @@ impl Provider for GitHubProvider {
         let mut latest_version = Version::new(0, 0, 0);
 
         // Gets the version from the release tag
-        for release in releases {
+        for release in releases.iter().filter(|r| !r.draft) {
             let version = release.version()?;
+            if !self.channel.includes(release.channel(&version)) {
+                continue;
+            }
             if version > latest_version {
                 latest_version = version;
             }
@@ -74,7 +155,7 @@ impl Provider for GitHubProvider {
     fn assets(&self, version: &Version) -> Result<Vec<&dyn Asset>, Box<dyn Error>> {
         let releases = self.releases()?;
 
-        for release in releases {
+        for release in releases.iter().filter(|r| !r.draft) {
             if release.version()? == *version {
                 return Ok(release.assets.iter().map(|x| x as &dyn Asset).collect());
             }
@@ -82,24 +163,23 @@ impl Provider for GitHubProvider {
 
         Err("Version not found".into())
     }
+}
 
-    fn asset(&self, version: &Version, name: &str) -> Result<Box<dyn Asset>, Box<dyn Error>> {
-        let assets = self.assets(version)?;
-
-        match assets.iter().find(|a| a.name() == name) {
-            Some(asset) => Ok(asset.box_clone()),
-            None => Err("Asset not found".into()),
-        }
-    }
-
-    fn find_asset(&self, version: &Version, name: &str) -> Result<Box<dyn Asset>, Box<dyn Error>> {
-        let assets = self.assets(version)?;
-
-        match assets.iter().find(|a| a.name().starts_with(name)) {
-            Some(asset) => Ok(asset.box_clone()),
-            None => Err("Asset not found".into()),
+/// Gets the url of the next page from a `Link` header
+fn next_page(link: &str) -> Option<String> {
+    link.split(',').find_map(|part| {
+        let (url, params) = part.split_once(';')?;
+        if params.split(';').any(|p| p.trim() == r#"rel="next""#) {
+            Some(
+                url.trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_string(),
+            )
+        } else {
+            None
         }
-    }
+    })
 }
 
 #[derive(Debug, Deserialize)]
@@ -120,6 +200,8 @@ struct GitHubRelease {
     name: String,
     tag_name: String,
     prerelease: bool,
+    #[serde(default)]
+    draft: bool,
     assets: Vec<GitHubAsset>,
 }
 
@@ -127,6 +209,14 @@ impl GitHubRelease {
     pub fn version(&self) -> Result<Version, Box<dyn Error>> {
         version::extract(&self.tag_name)
     }
+
+    /// Gets the channel from the version or from the prerelease flag
+    pub fn channel(&self, version: &Version) -> Channel {
+        match self.prerelease {
+            true => Channel::from_version(version).max(Channel::Beta),
+            false => Channel::from_version(version),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -134,6 +224,13 @@ struct GitHubAsset {
     name: String,
     size: u64,
     browser_download_url: String,
+    /// API endpoint of the asset
+    #[serde(rename = "url")]
+    api_url: String,
+    /// Hash of the asset (eg. "sha256:<hex encoded hash>")
+    digest: Option<String>,
+    #[serde(skip)]
+    token: Option<String>,
 }
 
 impl Asset for GitHubAsset {
@@ -146,10 +243,229 @@ impl Asset for GitHubAsset {
     }
 
     fn url(&self) -> &str {
-        &self.browser_download_url
+        match self.token {
+            Some(_) => &self.api_url,
+            None => &self.browser_download_url,
+        }
+    }
+
+    fn sha256(&self) -> Option<&str> {
+        self.digest.as_deref()?.strip_prefix("sha256:")
+    }
+
+    fn set_sha256(&mut self, sha256: String) {
+        self.digest = Some(format!("sha256:{}", sha256));
+    }
+
+    fn headers(&self) -> Vec<(String, String)> {
+        match self.token.as_ref() {
+            Some(token) => vec![
+                ("Accept".into(), "application/octet-stream".into()),
+                ("Authorization".into(), format!("Bearer {}", token)),
+            ],
+            None => Vec::new(),
+        }
     }
 
     fn box_clone(&self) -> Box<dyn Asset> {
         Box::new(self.clone())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::DownloadResult;
+    use crate::test_server::{Response, TestServer};
+    use crate::Progress;
+    use std::io::Read;
+    use std::sync::Arc;
+
+    const RELEASES: &str = r#"[
+        {
+            "name": "Release 0.2.0",
+            "tag_name": "v0.2.0",
+            "prerelease": false,
+            "assets": [{
+                "name": "app-linux-x64.tar.gz",
+                "size": 7,
+                "url": "{base}/api/v3/repos/user/private/releases/assets/1",
+                "digest": "sha256:715dc8493c36579a5b116995100f635e3572fdf8703e708ef1a08d943b36774e",
+                "browser_download_url": "{base}/user/private/releases/download/v0.2.0/app-linux-x64.tar.gz"
+            }]
+        }
+    ]"#;
+
+    fn server() -> TestServer {
+        TestServer::new(|request| {
+            let authorized = request.header("authorization") == Some("Bearer secret");
+            match request.path.split('?').next().unwrap() {
+                "/api/v3/repos/user/private/releases" if authorized => {
+                    let base = format!("http://{}", request.header("host").unwrap());
+                    Response::json(RELEASES.replace("{base}", &base))
+                }
+                "/api/v3/repos/user/private/releases/assets/1"
+                    if authorized
+                        && request.header("accept") == Some("application/octet-stream") =>
+                {
+                    Response::ok("private")
+                }
+                _ => Response::new(404, r#"{"message":"Not Found"}"#),
+            }
+        })
+    }
+
+    fn provider(server: &TestServer) -> GitHubProvider {
+        GitHubProvider::with_api_url(&server.url("/api/v3/"), "user/private")
+    }
+
+    #[test]
+    fn api_url() {
+        let provider = GitHubProvider::new("user/repo");
+        assert_eq!(
+            provider.url,
+            "https://api.github.com/repos/user/repo/releases"
+        );
+
+        let provider =
+            GitHubProvider::with_api_url("https://github.example.com/api/v3/", "user/repo");
+        assert_eq!(
+            provider.url,
+            "https://github.example.com/api/v3/repos/user/repo/releases"
+        );
+    }
+
+    #[test]
+    fn parse_link_header() {
+        let link = r#"<https://api.github.com/repositories/1/releases?per_page=100&page=2>; rel="next", <https://api.github.com/repositories/1/releases?per_page=100&page=5>; rel="last""#;
+        assert_eq!(
+            next_page(link).unwrap(),
+            "https://api.github.com/repositories/1/releases?per_page=100&page=2"
+        );
+
+        let link = r#"<https://api.github.com/repositories/1/releases?page=4>; rel="prev", <https://api.github.com/repositories/1/releases?page=1>; rel="first""#;
+        assert!(next_page(link).is_none());
+    }
+
+    fn release(tag: &str, prerelease: bool, draft: bool) -> String {
+        format!(
+            r#"{{ "name": "{0}", "tag_name": "{0}", "prerelease": {1}, "draft": {2}, "assets": [] }}"#,
+            tag, prerelease, draft
+        )
+    }
+
+    fn paged_server() -> TestServer {
+        TestServer::new(|request| {
+            let base = format!("http://{}", request.header("host").unwrap());
+            match request.path.as_str() {
+                "/repos/user/app/releases?per_page=100" => Response::json(format!(
+                    "[{}, {}]",
+                    release("v3.0.0", false, true),
+                    release("v2.1.0-rc.1", true, false)
+                ))
+                .with_header(
+                    "Link",
+                    &format!(r#"<{}/repositories/1/releases?page=2>; rel="next""#, base),
+                ),
+                "/repositories/1/releases?page=2" => {
+                    Response::json(format!("[{}]", release("v2.0.0", false, false))).with_header(
+                        "Link",
+                        &format!(r#"<{}/repositories/1/releases?page=1>; rel="prev""#, base),
+                    )
+                }
+                _ => Response::not_found(),
+            }
+        })
+    }
+
+    #[test]
+    fn follow_pages() {
+        let server = paged_server();
+        let mut provider = GitHubProvider::with_api_url(&server.base(), "user/app");
+        provider.fetch().unwrap();
+
+        assert_eq!(provider.releases().unwrap().len(), 3);
+        assert!(provider.assets(&Version::new(2, 0, 0)).is_ok());
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn latest_filters() {
+        let server = paged_server();
+
+        let mut provider = GitHubProvider::with_api_url(&server.base(), "user/app");
+        provider.fetch().unwrap();
+        assert_eq!(provider.latest().unwrap(), Version::new(2, 0, 0));
+        // Drafts can't be installed even if asked for
+        assert!(provider.assets(&Version::new(3, 0, 0)).is_err());
+
+        let mut provider =
+            GitHubProvider::with_api_url(&server.base(), "user/app").with_prereleases(true);
+        provider.fetch().unwrap();
+        assert_eq!(
+            provider.latest().unwrap(),
+            Version::parse("2.1.0-rc.1").unwrap()
+        );
+    }
+
+    #[test]
+    fn latest_in_channel() {
+        let server = TestServer::with_routes(vec![(
+            "/repos/user/app/releases",
+            Response::json(format!(
+                "[{}, {}, {}]",
+                release("v1.1.0-nightly.2", false, false),
+                release("v1.0.1", true, false),
+                release("v1.0.0", false, false)
+            )),
+        )]);
+        let mut provider = GitHubProvider::with_api_url(&server.base(), "user/app");
+        provider.fetch().unwrap();
+
+        assert_eq!(provider.latest().unwrap(), Version::new(1, 0, 0));
+        provider.set_channel(Channel::Beta);
+        assert_eq!(provider.latest().unwrap(), Version::new(1, 0, 1));
+        provider.set_channel(Channel::Nightly);
+        assert_eq!(
+            provider.latest().unwrap(),
+            Version::parse("1.1.0-nightly.2").unwrap()
+        );
+    }
+
+    #[test]
+    fn anonymous_private_repo() {
+        let server = server();
+        let mut provider = provider(&server);
+        assert!(provider.fetch().is_err());
+    }
+
+    #[test]
+    fn token_download() {
+        let server = server();
+        let mut provider = provider(&server).with_token("secret".into());
+        provider.fetch().unwrap();
+        assert_eq!(provider.latest().unwrap(), Version::new(0, 2, 0));
+
+        let asset = provider
+            .find_asset(&Version::new(0, 2, 0), "app-linux-x64")
+            .unwrap();
+        assert_eq!(
+            asset.url(),
+            server.url("/api/v3/repos/user/private/releases/assets/1")
+        );
+        assert_eq!(
+            asset.sha256(),
+            Some("715dc8493c36579a5b116995100f635e3572fdf8703e708ef1a08d943b36774e")
+        );
+
+        let progress = Arc::new(Progress::default());
+        let mut file = match asset.download(progress) {
+            DownloadResult::Complete(file) => file,
+            result => panic!("Unexpected download result: {:?}", result),
+        };
+
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "private");
+    }
+}
@@ -0,0 +1,318 @@
+use super::{http, Asset, Provider, ResponseCache};
+use crate::{version, Channel};
+use semver::Version;
+use serde::Deserialize;
+use std::error::Error;
+
+#[derive(Debug)]
+pub struct GitLabProvider {
+    url: String,
+    channel: Channel,
+    cache: Option<ResponseCache>,
+    releases: Option<Vec<GitLabRelease>>,
+}
+
+impl GitLabProvider {
+    /// Creates a new GitLabProvider for gitlab.com.
+    ///
+    /// * `project` should be "*namespace*/*project*" or the numeric project ID.
+    pub fn new(project: &str) -> Self {
+        Self::with_url("https://gitlab.com", project)
+    }
+
+    /// Creates a new GitLabProvider for a (self-hosted) GitLab instance.
+    ///
+    /// * `base_url` is the url of the instance (eg. "https://gitlab.example.com").
+    /// * `project` should be "*namespace*/*project*" or the numeric project ID.
+    pub fn with_url(base_url: &str, project: &str) -> Self {
+        Self {
+            url: format!(
+                "{}/api/v4/projects/{}/releases",
+                base_url.trim_end_matches('/'),
+                project.replace('/', "%2F")
+            ),
+            channel: Channel::Stable,
+            cache: None,
+            releases: None,
+        }
+    }
+
+    /// Gets the fetched data and returns it or Err if not.
+    fn releases(&self) -> Result<&Vec<GitLabRelease>, Box<dyn Error>> {
+        match self.releases.as_ref() {
+            Some(rel) => Ok(rel),
+            None => Err("No fetched content found!".into()),
+        }
+    }
+}
+
+impl Provider for GitLabProvider {
+    fn name(&self) -> &'static str {
+        "GitLab"
+    }
+
+    fn fetch(&mut self) -> Result<(), Box<dyn Error>> {
+        let request = http::agent()
+            .get(&self.url)
+            .set("Accept", "application/json");
+        let response = http::get(request, self.cache.as_ref())?;
+
+        let release: GitLabResponse = json::from_str(&response.body)?;
+
+        match release {
+            GitLabResponse::Release(release) => {
+                self.releases = Some(release);
+                Ok(())
+            }
+            GitLabResponse::Error(err) => Err(err.message.to_string().into()),
+        }
+    }
+
+    fn set_channel(&mut self, channel: Channel) {
+        self.channel = channel;
+    }
+
+    fn set_cache(&mut self, cache: ResponseCache) {
+        self.cache = Some(cache);
+    }
+
+    fn latest(&self) -> Result<Version, Box<dyn Error>> {
+        let releases = self.releases()?;
+
+        let mut latest_version = Version::new(0, 0, 0);
+
+        // Gets the version from the release tag
+        for release in releases.iter().filter(|r| !r.upcoming_release) {
+            let version = release.version()?;
+            if !self.channel.includes(Channel::from_version(&version)) {
+                continue;
+            }
+            if version > latest_version {
+                latest_version = version;
+            }
+        }
+
+        Ok(latest_version)
+    }
+
+    fn assets(&self, version: &Version) -> Result<Vec<&dyn Asset>, Box<dyn Error>> {
+        let releases = self.releases()?;
+
+        for release in releases {
+            if release.version()? == *version {
+                return Ok(release
+                    .assets
+                    .links
+                    .iter()
+                    .map(|x| x as &dyn Asset)
+                    .collect());
+            }
+        }
+
+        Err("Version not found".into())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum GitLabResponse {
+    Release(Vec<GitLabRelease>),
+    Error(GitLabError),
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabError {
+    /// Either a string or an object of field errors
+    message: json::Value,
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Deserialize)]
+struct GitLabRelease {
+    name: String,
+    tag_name: String,
+    #[serde(default)]
+    upcoming_release: bool,
+    assets: GitLabAssets,
+}
+
+impl GitLabRelease {
+    pub fn version(&self) -> Result<Version, Box<dyn Error>> {
+        version::extract(&self.tag_name)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GitLabAssets {
+    #[serde(default)]
+    links: Vec<GitLabLink>,
+}
+
+/// Release link. GitLab doesn't report the size of these.
+#[derive(Debug, Clone, Deserialize)]
+struct GitLabLink {
+    name: String,
+    url: String,
+    direct_asset_url: Option<String>,
+    #[serde(skip)]
+    sha256: Option<String>,
+}
+
+impl Asset for GitLabLink {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn size(&self) -> u64 {
+        0
+    }
+
+    fn url(&self) -> &str {
+        self.direct_asset_url.as_ref().unwrap_or(&self.url)
+    }
+
+    fn sha256(&self) -> Option<&str> {
+        self.sha256.as_deref()
+    }
+
+    fn set_sha256(&mut self, sha256: String) {
+        self.sha256 = Some(sha256);
+    }
+
+    fn box_clone(&self) -> Box<dyn Asset> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::DownloadResult;
+    use crate::test_server::{Response, TestServer};
+    use crate::Progress;
+    use std::io::Read;
+    use std::sync::Arc;
+
+    const RELEASES: &str = r#"[
+        {
+            "name": "Next",
+            "tag_name": "v2.0.0",
+            "upcoming_release": true,
+            "assets": { "count": 0, "sources": [], "links": [] }
+        },
+        {
+            "name": "Release 1.2.0 RC",
+            "tag_name": "v1.2.0-rc.1",
+            "assets": { "links": [] }
+        },
+        {
+            "name": "Release 1.1.0",
+            "tag_name": "v1.1.0",
+            "upcoming_release": false,
+            "assets": {
+                "count": 2,
+                "sources": [{ "format": "zip", "url": "{base}/archive.zip" }],
+                "links": [{
+                    "id": 2,
+                    "name": "app-linux-x64.tar.gz",
+                    "url": "{base}/links/2",
+                    "direct_asset_url": "{base}/files/app-linux-x64.tar.gz",
+                    "link_type": "package"
+                }]
+            }
+        },
+        {
+            "name": "Release 1.0.0",
+            "tag_name": "v1.0.0",
+            "assets": {
+                "links": [{ "name": "app-linux-x64.tar.gz", "url": "{base}/links/1" }]
+            }
+        }
+    ]"#;
+
+    fn server() -> TestServer {
+        TestServer::new(|request| match request.path.as_str() {
+            "/api/v4/projects/group%2Fapp/releases" => {
+                // Links point back to this server
+                let base = format!("http://{}", request.header("host").unwrap());
+                Response::json(RELEASES.replace("{base}", &base))
+            }
+            "/files/app-linux-x64.tar.gz" => Response::ok("payload"),
+            _ => Response::new(404, r#"{"message":"404 Project Not Found"}"#),
+        })
+    }
+
+    fn fetched(server: &TestServer) -> GitLabProvider {
+        let mut provider = GitLabProvider::with_url(&format!("{}/", server.base()), "group/app");
+        provider.fetch().unwrap();
+        provider
+    }
+
+    #[test]
+    fn latest_skips_upcoming() {
+        let server = server();
+        let mut provider = fetched(&server);
+        assert_eq!(provider.latest().unwrap(), Version::new(1, 1, 0));
+
+        provider.set_channel(Channel::Beta);
+        assert_eq!(
+            provider.latest().unwrap(),
+            Version::parse("1.2.0-rc.1").unwrap()
+        );
+    }
+
+    #[test]
+    fn links_are_assets() {
+        let server = server();
+        let provider = fetched(&server);
+
+        let asset = provider
+            .find_asset(&Version::new(1, 1, 0), "app-linux-x64")
+            .unwrap();
+        assert_eq!(asset.name(), "app-linux-x64.tar.gz");
+        assert_eq!(
+            asset.url(),
+            server.url("/files/app-linux-x64.tar.gz").as_str()
+        );
+
+        let asset = provider
+            .asset(&Version::new(1, 0, 0), "app-linux-x64.tar.gz")
+            .unwrap();
+        assert_eq!(asset.url(), server.url("/links/1").as_str());
+    }
+
+    #[test]
+    fn download_link() {
+        let server = server();
+        let provider = fetched(&server);
+        let asset = provider
+            .find_asset(&Version::new(1, 1, 0), "app-linux-x64")
+            .unwrap();
+
+        let progress = Arc::new(Progress::default());
+        let mut file = match asset.download(progress.clone()) {
+            DownloadResult::Complete(file) => file,
+            result => panic!("Unexpected download result: {:?}", result),
+        };
+
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "payload");
+        assert_eq!(progress.maximum(), 7);
+        assert_eq!(progress.current(), 7);
+    }
+
+    #[test]
+    fn fetch_missing_project() {
+        let server = server();
+        let mut provider = GitLabProvider::with_url(&server.base(), "group/missing");
+        assert!(provider.fetch().is_err());
+    }
+
+    #[test]
+    fn unknown_version() {
+        let server = server();
+        let provider = fetched(&server);
+        assert!(provider.assets(&Version::new(3, 0, 0)).is_err());
+    }
+}
@@ -0,0 +1,346 @@
+//! Provider for a static JSON manifest, for hosting updates on any file server or CDN.
+//!
+//! Manifest format:
+//! ```json
+//! {
+//!   "releases": [
+//!     {
+//!       "version": "1.2.0",
+//!       "channel": "stable",
+//!       "notes": "Fixed some bugs",
+//!       "assets": [
+//!         {
+//!           "name": "app-win-x64.zip",
+//!           "size": 1048576,
+//!           "url": "1.2.0/app-win-x64.zip",
+//!           "sha256": "<hex encoded hash>"
+//!         }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! * `channel` is optional ("stable", "beta" or "nightly") and derived from the version if missing.
+//! * `notes` and `sha256` are optional.
+//! * `url` can be absolute or relative to the manifest's url.
+
+use super::{http, Asset, Provider, ResponseCache};
+use crate::Channel;
+use semver::Version;
+use serde::Deserialize;
+use std::error::Error;
+
+#[derive(Debug)]
+pub struct ManifestProvider {
+    url: String,
+    channel: Channel,
+    cache: Option<ResponseCache>,
+    manifest: Option<Manifest>,
+}
+
+impl ManifestProvider {
+    /// Creates a new ManifestProvider.
+    ///
+    /// * `url` is the url of the manifest json file.
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            channel: Channel::Stable,
+            cache: None,
+            manifest: None,
+        }
+    }
+
+    /// Sets the release channel to look for the latest version in.
+    pub fn with_channel(mut self, channel: Channel) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    /// Returns the release notes of the specified release.
+    pub fn notes(&self, version: &Version) -> Result<Option<&str>, Box<dyn Error>> {
+        Ok(self.release(version)?.notes.as_deref())
+    }
+
+    /// Gets the fetched data and returns it or Err if not.
+    fn releases(&self) -> Result<&Vec<ManifestRelease>, Box<dyn Error>> {
+        match self.manifest.as_ref() {
+            Some(manifest) => Ok(&manifest.releases),
+            None => Err("No fetched content found!".into()),
+        }
+    }
+
+    fn release(&self, version: &Version) -> Result<&ManifestRelease, Box<dyn Error>> {
+        match self.releases()?.iter().find(|r| r.version == *version) {
+            Some(release) => Ok(release),
+            None => Err("Version not found".into()),
+        }
+    }
+}
+
+impl Provider for ManifestProvider {
+    fn name(&self) -> &'static str {
+        "Manifest"
+    }
+
+    fn fetch(&mut self) -> Result<(), Box<dyn Error>> {
+        let request = http::agent()
+            .get(&self.url)
+            .set("Accept", "application/json");
+        let response = http::get(request, self.cache.as_ref())?;
+
+        let mut manifest: Manifest = json::from_str(&response.body)?;
+
+        // Make every asset url absolute
+        for release in manifest.releases.iter_mut() {
+            for asset in release.assets.iter_mut() {
+                asset.url = resolve_url(&self.url, &asset.url);
+            }
+        }
+
+        self.manifest = Some(manifest);
+        Ok(())
+    }
+
+    fn set_channel(&mut self, channel: Channel) {
+        self.channel = channel;
+    }
+
+    fn set_cache(&mut self, cache: ResponseCache) {
+        self.cache = Some(cache);
+    }
+
+    fn latest(&self) -> Result<Version, Box<dyn Error>> {
+        let releases = self.releases()?;
+
+        let latest = releases
+            .iter()
+            .filter(|r| self.channel.includes(r.channel()))
+            .map(|r| &r.version)
+            .max();
+
+        match latest {
+            Some(version) => Ok(version.clone()),
+            None => Ok(Version::new(0, 0, 0)),
+        }
+    }
+
+    fn assets(&self, version: &Version) -> Result<Vec<&dyn Asset>, Box<dyn Error>> {
+        let release = self.release(version)?;
+        Ok(release.assets.iter().map(|x| x as &dyn Asset).collect())
+    }
+}
+
+/// Resolves a (possibly relative) url against the manifest's url
+fn resolve_url(base: &str, url: &str) -> String {
+    if url.contains("://") {
+        return url.to_string();
+    }
+
+    // Strip query string and fragment from the base
+    let base = base.split(['?', '#']).next().unwrap_or(base);
+
+    if let Some(path) = url.strip_prefix('/') {
+        // Relative to the host
+        let host_end = match base.find("://") {
+            Some(scheme_end) => base[scheme_end + 3..]
+                .find('/')
+                .map(|i| i + scheme_end + 3)
+                .unwrap_or(base.len()),
+            None => 0,
+        };
+        format!("{}/{}", &base[..host_end], path)
+    } else {
+        // Relative to the manifest's directory
+        match base.rfind('/') {
+            Some(i) => format!("{}/{}", &base[..i], url),
+            None => url.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    releases: Vec<ManifestRelease>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ManifestRelease {
+    version: Version,
+    channel: Option<Channel>,
+    notes: Option<String>,
+    #[serde(default)]
+    assets: Vec<ManifestAsset>,
+}
+
+impl ManifestRelease {
+    fn channel(&self) -> Channel {
+        self.channel
+            .unwrap_or_else(|| Channel::from_version(&self.version))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ManifestAsset {
+    name: String,
+    #[serde(default)]
+    size: u64,
+    url: String,
+    sha256: Option<String>,
+}
+
+impl Asset for ManifestAsset {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn sha256(&self) -> Option<&str> {
+        self.sha256.as_deref()
+    }
+
+    fn set_sha256(&mut self, sha256: String) {
+        self.sha256 = Some(sha256);
+    }
+
+    fn box_clone(&self) -> Box<dyn Asset> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Response, TestServer};
+
+    const MANIFEST: &str = r#"{
+        "releases": [
+            {
+                "version": "1.0.0",
+                "assets": [
+                    { "name": "app-linux-x64.tar.gz", "size": 10, "url": "/files/1.0.0.tar.gz" }
+                ]
+            },
+            {
+                "version": "1.1.0",
+                "channel": "stable",
+                "notes": "Bug fixes",
+                "assets": [
+                    {
+                        "name": "app-linux-x64.tar.gz",
+                        "size": 11,
+                        "url": "1.1.0/app-linux-x64.tar.gz",
+                        "sha256": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+                    },
+                    { "name": "app-win-x64.zip", "size": 12, "url": "https://cdn.example.com/app-win-x64.zip" }
+                ]
+            },
+            {
+                "version": "1.2.0-beta.1",
+                "channel": "beta",
+                "assets": []
+            },
+            {
+                "version": "1.3.0-nightly.1",
+                "assets": []
+            }
+        ]
+    }"#;
+
+    fn fetched(server: &TestServer, channel: Channel) -> ManifestProvider {
+        let mut provider =
+            ManifestProvider::new(&server.url("/updates/manifest.json")).with_channel(channel);
+        provider.fetch().unwrap();
+        provider
+    }
+
+    fn server() -> TestServer {
+        TestServer::with_routes(vec![("/updates/manifest.json", Response::json(MANIFEST))])
+    }
+
+    #[test]
+    fn latest_in_channel() {
+        let server = server();
+        assert_eq!(
+            fetched(&server, Channel::Stable).latest().unwrap(),
+            Version::new(1, 1, 0)
+        );
+        assert_eq!(
+            fetched(&server, Channel::Beta).latest().unwrap(),
+            Version::parse("1.2.0-beta.1").unwrap()
+        );
+        assert_eq!(
+            fetched(&server, Channel::Nightly).latest().unwrap(),
+            Version::parse("1.3.0-nightly.1").unwrap()
+        );
+    }
+
+    #[test]
+    fn asset_urls_resolved() {
+        let server = server();
+        let provider = fetched(&server, Channel::Stable);
+        let version = Version::new(1, 1, 0);
+
+        let asset = provider.find_asset(&version, "app-linux").unwrap();
+        assert_eq!(asset.size(), 11);
+        assert_eq!(
+            asset.url(),
+            server.url("/updates/1.1.0/app-linux-x64.tar.gz")
+        );
+
+        assert_eq!(
+            asset.sha256(),
+            Some("e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855")
+        );
+
+        let asset = provider.asset(&version, "app-win-x64.zip").unwrap();
+        assert_eq!(asset.url(), "https://cdn.example.com/app-win-x64.zip");
+        assert_eq!(asset.sha256(), None);
+
+        let asset = provider
+            .asset(&Version::new(1, 0, 0), "app-linux-x64.tar.gz")
+            .unwrap();
+        assert_eq!(asset.url(), server.url("/files/1.0.0.tar.gz"));
+    }
+
+    #[test]
+    fn release_notes() {
+        let server = server();
+        let provider = fetched(&server, Channel::Stable);
+        assert_eq!(
+            provider.notes(&Version::new(1, 1, 0)).unwrap(),
+            Some("Bug fixes")
+        );
+        assert_eq!(provider.notes(&Version::new(1, 0, 0)).unwrap(), None);
+        assert!(provider.notes(&Version::new(9, 0, 0)).is_err());
+    }
+
+    #[test]
+    fn invalid_manifest() {
+        let server = TestServer::with_routes(vec![(
+            "/manifest.json",
+            Response::json(r#"{ "releases": [{ "version": "one" }] }"#),
+        )]);
+        let mut provider = ManifestProvider::new(&server.url("/manifest.json"));
+        assert!(provider.fetch().is_err());
+    }
+
+    #[test]
+    fn resolve_urls() {
+        let base = "https://example.com/a/b/manifest.json?x=1";
+        assert_eq!(resolve_url(base, "c.zip"), "https://example.com/a/b/c.zip");
+        assert_eq!(resolve_url(base, "/c.zip"), "https://example.com/c.zip");
+        assert_eq!(
+            resolve_url(base, "http://other.com/c.zip"),
+            "http://other.com/c.zip"
+        );
+    }
+}
@@ -1,11 +1,31 @@
+mod checksum;
+pub mod directory;
+pub mod fallback;
+pub mod gitea;
 pub mod github;
+pub mod gitlab;
+pub mod http;
+pub mod manifest;
+mod resume;
 
+pub(crate) use checksum::to_hex;
+pub use checksum::ChecksumMismatch;
+#[cfg(feature = "delta")]
+pub(crate) use checksum::HashWriter;
+pub use directory::DirectoryProvider;
+pub use fallback::FallbackProvider;
+pub use gitea::GiteaProvider;
 pub use github::GitHubProvider;
+pub use gitlab::GitLabProvider;
+pub use http::{set_http_config, HttpConfig, RateLimited, ResponseCache, RetryPolicy};
+pub use manifest::ManifestProvider;
 
-use crate::Progress;
+use crate::{Channel, Progress};
 use semver::Version;
 use std::error::Error;
 use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
 use std::sync::Arc;
 
 #[derive(Debug)]
@@ -22,87 +42,241 @@ pub trait Provider {
     /// Fetches all necessary data for the provider.
     fn fetch(&mut self) -> Result<(), Box<dyn Error>>;
 
-    /// Returns the latest version available by the provider.
+    /// Sets the release channel the latest version is looked for in.
+    /// No-op for providers without channels.
+    fn set_channel(&mut self, _channel: Channel) {}
+
+    /// Sets the cache used for conditional requests. No-op for non-HTTP providers.
+    fn set_cache(&mut self, _cache: ResponseCache) {}
+
+    /// Returns the latest version available by the provider in the selected channel.
     fn latest(&self) -> Result<Version, Box<dyn Error>>;
 
     /// Returns the downloadable assets of the specified release.
     fn assets(&self, version: &Version) -> Result<Vec<&dyn Asset>, Box<dyn Error>>;
 
     /// Returns the downloadable asset with the specified name from the specified release.
-    fn asset(&self, version: &Version, name: &str) -> Result<Box<dyn Asset>, Box<dyn Error>>;
+    fn asset(&self, version: &Version, name: &str) -> Result<Box<dyn Asset>, Box<dyn Error>> {
+        let assets = self.assets(version)?;
+
+        match assets.iter().find(|a| a.name() == name) {
+            Some(asset) => Ok(asset.box_clone()),
+            None => Err("Asset not found".into()),
+        }
+    }
 
     /// Searches and returns the asset from the specified release.
-    fn find_asset(&self, version: &Version, name: &str) -> Result<Box<dyn Asset>, Box<dyn Error>>;
+    fn find_asset(&self, version: &Version, name: &str) -> Result<Box<dyn Asset>, Box<dyn Error>> {
+        let assets = self.assets(version)?;
+
+        match assets
+            .iter()
+            .find(|a| a.name().starts_with(name) && !is_sidecar(a.name()))
+        {
+            Some(asset) => Ok(asset.box_clone()),
+            None => Err("Asset not found".into()),
+        }
+    }
+
+    /// Gets the SHA-256 hash (hex encoded) of the asset from a checksum file of the release
+    /// (eg. "SHA256SUMS" or "<name>.sha256"). Ok(None) if the release has no such file.
+    fn checksum(&self, version: &Version, name: &str) -> Result<Option<String>, Box<dyn Error>> {
+        checksum::from_sidecar(&self.assets(version)?, name)
+    }
+
+    /// Searches the patch which updates the asset from an older version to the specified release.
+    /// Patches are published among the assets of the new release (see `patch_name`).
+    fn find_patch(
+        &self,
+        version: &Version,
+        from: &Version,
+        name: &str,
+    ) -> Result<Option<Box<dyn Asset>>, Box<dyn Error>> {
+        let patch_name = patch_name(from, name);
+        let assets = self.assets(version)?;
+        Ok(assets
+            .iter()
+            .find(|a| a.name() == patch_name)
+            .map(|a| a.box_clone()))
+    }
+
+    /// Searches the list of the files in the asset with their hashes (see `file_list_name`).
+    fn find_file_list(
+        &self,
+        version: &Version,
+        name: &str,
+    ) -> Result<Option<Box<dyn Asset>>, Box<dyn Error>> {
+        let list_name = file_list_name(name);
+        let assets = self.assets(version)?;
+        Ok(assets
+            .iter()
+            .find(|a| a.name() == list_name)
+            .map(|a| a.box_clone()))
+    }
 }
 
 pub trait Asset: Send {
     /// Gets the name of the asset
     fn name(&self) -> &str;
 
-    /// Gets the size of the asset in bytes
+    /// Gets the size of the asset in bytes (0 if unknown)
     fn size(&self) -> u64;
 
     /// Gets the url of the asset
     fn url(&self) -> &str;
 
+    /// Gets the expected SHA-256 hash (hex encoded) of the asset if known.
+    /// Downloads are verified against it.
+    fn sha256(&self) -> Option<&str>;
+
+    /// Sets the expected SHA-256 hash (hex encoded) of the asset
+    fn set_sha256(&mut self, sha256: String);
+
+    /// Gets the extra http headers needed to download the asset
+    fn headers(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
     /// Clone into a Box
     fn box_clone(&self) -> Box<dyn Asset>;
 
+    /// Opens the content of the asset for reading, to process it without a temporary file.
+    /// Returns the reader and the size of the content (0 if unknown).
+    fn open(&self) -> Result<(Box<dyn Read + Send>, u64), Box<dyn Error>> {
+        // Send request message
+        let mut request = http::agent().get(self.url());
+        for (name, value) in self.headers() {
+            request = request.set(&name, &value);
+        }
+        let response = http::call(request, &RetryPolicy::default())?;
+
+        // Fall back to the reported content length if the provider doesn't know the size
+        let size = match self.size() {
+            0 => response
+                .header("Content-Length")
+                .and_then(|len| len.parse().ok())
+                .unwrap_or(0),
+            size => size,
+        };
+
+        Ok((Box::new(response.into_reader()), size))
+    }
+
     /// Download the asset into a temprary file on a separate thread
     fn download(&self, progress: Arc<Progress>) -> DownloadResult {
-        use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+        let (reader, size) = match self.open() {
+            Ok(opened) => opened,
+            Err(e) => return DownloadResult::Error(e),
+        };
 
         log::info!(
             "Downloading {} - {:.2}MB",
             self.name(),
-            self.size() as f64 / 1_000_000.0
+            size as f64 / 1_000_000.0
         );
 
         // Setup progress
-        progress.set_maximum(self.size());
-        progress.set_indeterminate(false);
+        progress.set_maximum(size);
+        progress.set_indeterminate(size == 0);
 
-        // Send request message
-        let response = ureq::get(self.url()).call();
-        if response.is_err() {
-            return DownloadResult::Error("Response not OK".into());
-        }
+        download_into_tempfile(reader, progress, self.sha256())
+    }
+
+    /// Download the asset into a partial file in the directory on a separate thread.
+    /// An interrupted or cancelled download is kept and resumed next time
+    /// if the server supports range requests.
+    fn download_resumable(&self, dir: &Path, progress: Arc<Progress>) -> DownloadResult {
+        resume::download(self, dir, progress)
+    }
+}
+
+/// Gets the name of the patch which updates the asset from the specified version.
+/// eg. "1.1.0-app-win-x64.zip.patch" updates "app-win-x64.zip" from v1.1.0.
+pub fn patch_name(from: &Version, name: &str) -> String {
+    format!("{}-{}.patch", from, name)
+}
+
+/// Gets the name of the list of the files in the asset.
+/// eg. "app-win-x64.zip.files.json" for "app-win-x64.zip".
+pub fn file_list_name(name: &str) -> String {
+    format!("{}.files.json", name)
+}
+
+/// Checks if the asset only describes another asset (signature, checksum, patch or file list)
+fn is_sidecar(name: &str) -> bool {
+    [".minisig", ".sha256", ".sha256sum", ".patch", ".files.json"]
+        .iter()
+        .any(|ext| name.ends_with(ext))
+}
 
-        // Init reader and temp file
-        let mut reader = response.unwrap().into_reader(); // 'response' checked above
-        let mut out = match tempfile::tempfile() {
+/// Copies the content of the reader into a temporary file while reporting progress.
+/// The content is verified against the SHA-256 hash if specified.
+pub(crate) fn download_into_tempfile<R: Read>(
+    mut reader: R,
+    progress: Arc<Progress>,
+    sha256: Option<&str>,
+) -> DownloadResult {
+    use std::io::{Seek, SeekFrom};
+
+    // Init temp file
+    let mut out = match tempfile::tempfile() {
+        Ok(file) => checksum::HashWriter::new(file),
+        Err(e) => return DownloadResult::Error(e.into()),
+    };
+
+    // Copy received data into temp file
+    match copy_with_progress(&mut reader, &mut out, &progress) {
+        Ok(true) => {}
+        Ok(false) => return DownloadResult::Cancelled,
+        Err(e) => return DownloadResult::Error(e.into()),
+    }
+
+    let mut out = match sha256 {
+        Some(sha256) => match out.verify(sha256) {
             Ok(file) => file,
             Err(e) => return DownloadResult::Error(e.into()),
-        };
+        },
+        None => out.into_inner(),
+    };
 
-        // Copy received data into temo file
-        let mut buf = [0; 16384];
-        loop {
-            if progress.cancelled() {
-                return DownloadResult::Cancelled;
-            }
+    // Reset temp file
+    if let Err(e) = out.seek(SeekFrom::Start(0)) {
+        return DownloadResult::Error(e.into());
+    };
 
-            let len = match reader.read(&mut buf) {
-                Ok(0) => break,
-                Ok(len) => len,
-                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
-                Err(e) => return DownloadResult::Error(e.into()),
-            };
-
-            if let Err(e) = out.write_all(&buf[..len]) {
-                return DownloadResult::Error(e.into());
-            };
-            progress.add_current(len as u64);
+    DownloadResult::Complete(out)
+}
+
+/// Copies the content of the reader into the writer while reporting progress.
+/// Returns false if cancelled.
+pub(crate) fn copy_with_progress<R: Read, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    progress: &Progress,
+) -> std::io::Result<bool> {
+    use std::io::ErrorKind;
+
+    let mut buf = [0; 16384];
+    loop {
+        if progress.cancelled() {
+            writer.flush()?;
+            return Ok(false);
         }
 
-        // Flush and reset temp file
-        if let Err(e) = out.flush() {
-            return DownloadResult::Error(e.into());
-        };
-        if let Err(e) = out.seek(SeekFrom::Start(0)) {
-            return DownloadResult::Error(e.into());
+        let len = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => {
+                writer.flush()?;
+                return Err(e);
+            }
         };
 
-        DownloadResult::Complete(out)
+        writer.write_all(&buf[..len])?;
+        progress.add_current(len as u64);
     }
+
+    writer.flush()?;
+    Ok(true)
 }
@@ -0,0 +1,479 @@
+//! Resumable downloads into partial files.
+//!
+//! The partial file (`<name>.part`) is stored with the validators of the response
+//! (`<name>.part.json`). A later download of the same url continues it with a `Range` request,
+//! guarded by `If-Range`, and starts over if the content has changed in the meantime.
+
+use super::checksum::HashWriter;
+use super::{copy_with_progress, http, Asset, DownloadResult, RetryPolicy};
+use crate::Progress;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::{File, OpenOptions};
+use std::io::{ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Validators of a partial download
+#[derive(Debug, Serialize, Deserialize)]
+struct PartialInfo {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    /// Full size of the asset (0 if unknown)
+    size: u64,
+}
+
+/// Paths of a partial download
+struct Partial {
+    path: PathBuf,
+    info_path: PathBuf,
+}
+
+impl Partial {
+    fn new(dir: &Path, name: &str) -> Self {
+        let name = name.replace(['/', '\\'], "_");
+        Self {
+            path: dir.join(format!("{}.part", name)),
+            info_path: dir.join(format!("{}.part.json", name)),
+        }
+    }
+
+    /// Loads the validators if the partial file can be continued
+    fn load<A: Asset + ?Sized>(&self, asset: &A) -> Option<(PartialInfo, u64)> {
+        let len = self.path.metadata().ok()?.len();
+        let info: PartialInfo = json::from_slice(&std::fs::read(&self.info_path).ok()?).ok()?;
+
+        let same_size = asset.size() == 0 || info.size == 0 || asset.size() == info.size;
+        let validated = info.etag.is_some() || info.last_modified.is_some() || info.size != 0;
+        if info.url != asset.url() || !same_size || !validated || len == 0 {
+            return None;
+        }
+
+        Some((info, len))
+    }
+
+    fn store(&self, info: &PartialInfo) -> Result<(), Box<dyn Error>> {
+        std::fs::write(&self.info_path, json::to_vec(info)?)?;
+        Ok(())
+    }
+
+    /// Removes the partial file and its validators
+    fn remove(&self) -> std::io::Result<()> {
+        for path in [&self.path, &self.info_path] {
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Downloads the asset into a partial file in the directory, continuing a previous download.
+pub(crate) fn download<A: Asset + ?Sized>(
+    asset: &A,
+    dir: &Path,
+    progress: Arc<Progress>,
+) -> DownloadResult {
+    match try_download(asset, dir, &progress) {
+        Ok(result) => result,
+        Err(e) => DownloadResult::Error(e),
+    }
+}
+
+fn try_download<A: Asset + ?Sized>(
+    asset: &A,
+    dir: &Path,
+    progress: &Progress,
+) -> Result<DownloadResult, Box<dyn Error>> {
+    std::fs::create_dir_all(dir)?;
+    let partial = Partial::new(dir, asset.name());
+    let policy = RetryPolicy::default();
+    let mut retry = 0;
+
+    let (size, out) = loop {
+        let previous = partial.load(asset);
+        if previous.is_none() {
+            partial.remove()?;
+        }
+
+        let mut request = http::agent().get(asset.url());
+        for (name, value) in asset.headers() {
+            request = request.set(&name, &value);
+        }
+        if let Some((info, len)) = previous.as_ref() {
+            request = request.set("Range", &format!("bytes={}-", len));
+            if let Some(validator) = info.etag.as_ref().or(info.last_modified.as_ref()) {
+                request = request.set("If-Range", validator);
+            }
+        }
+
+        let response = match http::call(request, &policy) {
+            Ok(response) => response,
+            Err(e) if previous.is_some() && is_range_error(e.as_ref()) => {
+                debug!(
+                    "Range not satisfiable, restarting download of {}",
+                    asset.name()
+                );
+                partial.remove()?;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let (offset, size) = match (response.status(), previous) {
+            (206, Some((info, len))) => match content_range(&response) {
+                Some((start, total)) if start == len && (info.size == 0 || total == info.size) => {
+                    (len, total)
+                }
+                _ => {
+                    debug!("Unexpected range, restarting download of {}", asset.name());
+                    partial.remove()?;
+                    continue;
+                }
+            },
+            _ => {
+                let size = response
+                    .header("Content-Length")
+                    .and_then(|len| len.parse().ok())
+                    .unwrap_or_else(|| asset.size());
+                partial.store(&PartialInfo {
+                    url: asset.url().to_string(),
+                    etag: response.header("ETag").map(String::from),
+                    last_modified: response.header("Last-Modified").map(String::from),
+                    size,
+                })?;
+                (0, size)
+            }
+        };
+
+        if offset > 0 {
+            info!(
+                "Resuming download of {} at {:.2}/{:.2}MB",
+                asset.name(),
+                offset as f64 / 1_000_000.0,
+                size as f64 / 1_000_000.0
+            );
+        } else {
+            info!(
+                "Downloading {} - {:.2}MB",
+                asset.name(),
+                size as f64 / 1_000_000.0
+            );
+        }
+
+        // Setup progress
+        progress.set_maximum(size);
+        progress.set_indeterminate(size == 0);
+        progress.set_current(offset);
+
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(offset > 0)
+            .truncate(offset == 0)
+            .open(&partial.path)?;
+
+        // The hash continues from the already downloaded part
+        let mut out = HashWriter::new(file);
+        if offset > 0 && asset.sha256().is_some() {
+            out.update(File::open(&partial.path)?.take(offset))?;
+        }
+
+        match copy_with_progress(&mut response.into_reader(), &mut out, progress) {
+            Ok(true) => break (size, out),
+            Ok(false) => return Ok(DownloadResult::Cancelled),
+            Err(e) if retry < policy.max_retries => {
+                let wait = policy.backoff(retry);
+                warn!(
+                    "Download of {} interrupted: {}. Resuming in {:.1}s",
+                    asset.name(),
+                    e,
+                    wait.as_secs_f64()
+                );
+                std::thread::sleep(wait);
+                retry += 1;
+            }
+            Err(e) => return Err(e.into()),
+        }
+    };
+
+    let len = partial.path.metadata()?.len();
+    if size != 0 && len != size {
+        partial.remove()?;
+        return Err(format!("Downloaded {} bytes instead of {}", len, size).into());
+    }
+
+    if let Some(sha256) = asset.sha256() {
+        if let Err(e) = out.verify(sha256) {
+            partial.remove()?;
+            return Err(e.into());
+        }
+    }
+
+    // The open file stays readable after its removal
+    let file = File::open(&partial.path)?;
+    partial.remove()?;
+
+    Ok(DownloadResult::Complete(file))
+}
+
+/// Checks if the error is a "416 Range Not Satisfiable" response
+fn is_range_error(error: &(dyn Error + 'static)) -> bool {
+    matches!(
+        error.downcast_ref::<ureq::Error>(),
+        Some(ureq::Error::Status(416, _))
+    )
+}
+
+/// Parses the start and the full size from the `Content-Range` header ("bytes 10-99/100")
+fn content_range(response: &ureq::Response) -> Option<(u64, u64)> {
+    let range = response.header("Content-Range")?.strip_prefix("bytes ")?;
+    let (range, total) = range.split_once('/')?;
+    let (start, _) = range.split_once('-')?;
+    let total = match total.trim() {
+        "*" => 0,
+        total => total.parse().ok()?,
+    };
+    Some((start.trim().parse().ok()?, total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Request, Response, TestServer};
+    use std::io::Read;
+    use std::time::Duration;
+
+    const CONTENT: &str = "Hello resumable world!";
+    const ETAG: &str = "\"v1\"";
+
+    /// Serves the content with range support like a typical CDN
+    fn serve(request: &Request) -> Response {
+        let range = request
+            .header("range")
+            .and_then(|range| range.strip_prefix("bytes="))
+            .and_then(|range| range.strip_suffix('-'))
+            .and_then(|start| start.parse::<usize>().ok());
+        let if_range = request.header("if-range");
+
+        match range {
+            Some(start) if if_range.is_none() || if_range == Some(ETAG) => {
+                if start >= CONTENT.len() {
+                    return Response::new(416, "");
+                }
+                Response::new(206, &CONTENT[start..])
+                    .with_header("ETag", ETAG)
+                    .with_header(
+                        "Content-Range",
+                        &format!("bytes {}-{}/{}", start, CONTENT.len() - 1, CONTENT.len()),
+                    )
+            }
+            _ => Response::ok(CONTENT).with_header("ETag", ETAG),
+        }
+    }
+
+    #[derive(Clone)]
+    struct TestAsset {
+        url: String,
+    }
+
+    impl Asset for TestAsset {
+        fn name(&self) -> &str {
+            "app.zip"
+        }
+
+        fn size(&self) -> u64 {
+            0
+        }
+
+        fn url(&self) -> &str {
+            &self.url
+        }
+
+        fn sha256(&self) -> Option<&str> {
+            None
+        }
+
+        fn set_sha256(&mut self, _sha256: String) {}
+
+        fn box_clone(&self) -> Box<dyn Asset> {
+            Box::new(self.clone())
+        }
+    }
+
+    fn directory(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        dir
+    }
+
+    fn partial(dir: &Path, url: &str, content: &str, etag: &str) -> Partial {
+        std::fs::create_dir_all(dir).unwrap();
+        let partial = Partial::new(dir, "app.zip");
+        std::fs::write(&partial.path, content).unwrap();
+        partial
+            .store(&PartialInfo {
+                url: url.to_string(),
+                etag: Some(etag.to_string()),
+                last_modified: None,
+                size: CONTENT.len() as u64,
+            })
+            .unwrap();
+        partial
+    }
+
+    fn content(result: DownloadResult) -> String {
+        let mut file = match result {
+            DownloadResult::Complete(file) => file,
+            result => panic!("Unexpected download result: {:?}", result),
+        };
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    fn fresh_download() {
+        let server = TestServer::new(serve);
+        let dir = directory("updater_test_resume_fresh");
+        let asset = TestAsset {
+            url: server.url("/app.zip"),
+        };
+
+        let progress = Arc::new(Progress::default());
+        assert_eq!(
+            content(asset.download_resumable(&dir, progress.clone())),
+            CONTENT
+        );
+        assert_eq!(progress.current(), CONTENT.len() as u64);
+        assert_eq!(server.requests()[0].header("range"), None);
+
+        // Nothing is left behind
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resume_partial() {
+        let server = TestServer::new(serve);
+        let dir = directory("updater_test_resume_partial");
+        let url = server.url("/app.zip");
+        partial(&dir, &url, "Hello ", ETAG);
+
+        let progress = Arc::new(Progress::default());
+        let asset = TestAsset { url };
+        assert_eq!(
+            content(asset.download_resumable(&dir, progress.clone())),
+            CONTENT
+        );
+        assert_eq!(progress.current(), CONTENT.len() as u64);
+
+        let request = &server.requests()[0];
+        assert_eq!(request.header("range"), Some("bytes=6-"));
+        assert_eq!(request.header("if-range"), Some(ETAG));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn restart_changed() {
+        let server = TestServer::new(serve);
+        let dir = directory("updater_test_resume_changed");
+        let url = server.url("/app.zip");
+        partial(&dir, &url, "Outdated content", "\"v0\"");
+
+        let asset = TestAsset { url };
+        let result = asset.download_resumable(&dir, Arc::new(Progress::default()));
+        assert_eq!(content(result), CONTENT);
+
+        // A different url starts over without a range request
+        partial(&dir, &server.url("/old.zip"), "Hello ", ETAG);
+        let result = asset.download_resumable(&dir, Arc::new(Progress::default()));
+        assert_eq!(content(result), CONTENT);
+        assert_eq!(server.requests()[1].header("range"), None);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn restart_not_satisfiable() {
+        let server = TestServer::new(serve);
+        let dir = directory("updater_test_resume_416");
+        let url = server.url("/app.zip");
+        partial(&dir, &url, &format!("{}!!!", CONTENT), ETAG);
+
+        let asset = TestAsset { url };
+        let result = asset.download_resumable(&dir, Arc::new(Progress::default()));
+        assert_eq!(content(result), CONTENT);
+        assert_eq!(server.requests().len(), 2);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keep_cancelled() {
+        let server = TestServer::new(serve);
+        let dir = directory("updater_test_resume_cancel");
+        let asset = TestAsset {
+            url: server.url("/app.zip"),
+        };
+
+        let progress = Arc::new(Progress::default());
+        progress.set_cancelled(true);
+        assert!(matches!(
+            asset.download_resumable(&dir, progress),
+            DownloadResult::Cancelled
+        ));
+
+        let partial = Partial::new(&dir, "app.zip");
+        assert!(partial.path.exists());
+        assert!(partial.info_path.exists());
+
+        let result = asset.download_resumable(&dir, Arc::new(Progress::default()));
+        assert_eq!(content(result), CONTENT);
+        assert!(!partial.path.exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn resume_interrupted() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let count = AtomicUsize::new(0);
+        let server = TestServer::new(move |request| match count.fetch_add(1, Ordering::AcqRel) {
+            0 => serve(request).interrupted(6),
+            _ => serve(request),
+        });
+        let dir = directory("updater_test_resume_interrupted");
+        let asset = TestAsset {
+            url: server.url("/app.zip"),
+        };
+
+        // Resumed in the same call after the first backoff
+        let started = std::time::Instant::now();
+        let result = asset.download_resumable(&dir, Arc::new(Progress::default()));
+        assert_eq!(content(result), CONTENT);
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].header("range"), Some("bytes=6-"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn parse_content_range() {
+        let server = TestServer::new(|_| {
+            Response::new(206, "").with_header("Content-Range", "bytes 10-99/100")
+        });
+        let response = ureq::get(&server.url("/")).call().unwrap();
+        assert_eq!(content_range(&response), Some((10, 100)));
+    }
+}
@@ -0,0 +1,422 @@
+//! Minisign (ed25519) signature verification of the release assets.
+//!
+//! The signature of an asset is looked for in the same release as `<asset name>.minisig`.
+//! The `file:` field of its trusted comment has to match the asset name,
+//! so the signature of another asset can't be passed off for it.
+
+use crate::provider::{DownloadResult, Provider};
+use crate::Progress;
+use log::{info, warn};
+use minisign_verify::Signature;
+use semver::Version;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Signature files bigger than this are rejected
+const MAX_SIGNATURE_SIZE: usize = 16_384;
+
+/// Public key the assets are signed with
+#[derive(Debug, Clone)]
+pub struct PublicKey(minisign_verify::PublicKey);
+
+impl FromStr for PublicKey {
+    type Err = Box<dyn Error>;
+
+    /// Parses either the base64 encoded key or the content of a minisign public key file
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let key = match s.contains('\n') {
+            true => minisign_verify::PublicKey::decode(s),
+            false => minisign_verify::PublicKey::from_base64(s),
+        };
+
+        match key {
+            Ok(key) => Ok(Self(key)),
+            Err(e) => Err(format!("Invalid public key: {}", e).into()),
+        }
+    }
+}
+
+/// Error returned when an asset isn't signed properly
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureError {
+    /// The release has no signature of the asset
+    Missing(String),
+    /// The signature is malformed or doesn't match the asset or the key
+    Invalid(String),
+}
+
+impl Display for SignatureError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureError::Missing(name) => write!(f, "{} is not signed", name),
+            SignatureError::Invalid(reason) => write!(f, "Invalid signature: {}", reason),
+        }
+    }
+}
+
+impl Error for SignatureError {}
+
+/// Verifies the downloaded asset with the signature from its release.
+///
+/// * `allow_unsigned` - Accept the asset if the release has no signature of it.
+///   Invalid signatures are always rejected.
+///
+/// The file is rewound afterwards.
+pub fn verify_asset(
+    provider: &dyn Provider,
+    version: &Version,
+    name: &str,
+    file: &mut File,
+    public_key: &PublicKey,
+    allow_unsigned: bool,
+) -> Result<(), Box<dyn Error>> {
+    let signature = match AssetSignature::download(provider, version, name, allow_unsigned)? {
+        Some(signature) => signature,
+        None => return Ok(()),
+    };
+
+    file.seek(SeekFrom::Start(0))?;
+    verify(&mut *file, &signature.signature, public_key)?;
+    signature.check_file_name()?;
+    file.seek(SeekFrom::Start(0))?;
+
+    signature.log_verified();
+    Ok(())
+}
+
+/// Signature of an asset, downloaded before the asset to verify it while it's streamed
+pub struct AssetSignature {
+    name: String,
+    signature: Signature,
+}
+
+impl AssetSignature {
+    /// Downloads the signature of the asset. Ok(None) if it's not signed and that's allowed.
+    pub fn download(
+        provider: &dyn Provider,
+        version: &Version,
+        name: &str,
+        allow_unsigned: bool,
+    ) -> Result<Option<Self>, Box<dyn Error>> {
+        match download_signature(provider, version, name)? {
+            Some(signature) => Ok(Some(Self {
+                name: name.to_string(),
+                signature,
+            })),
+            None if allow_unsigned => {
+                warn!("{} is not signed, installing it anyway", name);
+                Ok(None)
+            }
+            None => Err(SignatureError::Missing(name.to_string()).into()),
+        }
+    }
+
+    /// Creates a verifier for the content. Legacy signatures can't be verified in a stream.
+    pub fn verifier<'a>(
+        &'a self,
+        public_key: &'a PublicKey,
+    ) -> Result<StreamVerifier<'a>, Box<dyn Error>> {
+        match public_key.0.verify_stream(&self.signature) {
+            Ok(verifier) => Ok(StreamVerifier {
+                verifier,
+                signature: self,
+            }),
+            Err(e) => Err(SignatureError::Invalid(e.to_string()).into()),
+        }
+    }
+
+    /// Checks that the (verified) trusted comment was signed for this asset
+    fn check_file_name(&self) -> Result<(), SignatureError> {
+        let trusted_comment = self.signature.trusted_comment();
+        let file = trusted_comment
+            .split('\t')
+            .find_map(|field| field.strip_prefix("file:"));
+
+        match file {
+            Some(file) if file != self.name => Err(SignatureError::Invalid(format!(
+                "signature is for {} instead of {}",
+                file, self.name
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    fn log_verified(&self) {
+        info!(
+            "Signature of {} verified ({})",
+            self.name,
+            self.signature.trusted_comment()
+        );
+    }
+}
+
+/// Verifies the signature of the content passed to it in chunks
+pub struct StreamVerifier<'a> {
+    verifier: minisign_verify::StreamVerifier<'a>,
+    signature: &'a AssetSignature,
+}
+
+impl StreamVerifier<'_> {
+    pub fn update(&mut self, buf: &[u8]) {
+        self.verifier.update(buf);
+    }
+
+    /// Checks the signature of all the content passed
+    pub fn finalize(mut self) -> Result<(), Box<dyn Error>> {
+        self.verifier
+            .finalize()
+            .map_err(|e| SignatureError::Invalid(e.to_string()))?;
+        self.signature.check_file_name()?;
+        self.signature.log_verified();
+        Ok(())
+    }
+}
+
+fn download_signature(
+    provider: &dyn Provider,
+    version: &Version,
+    name: &str,
+) -> Result<Option<Signature>, Box<dyn Error>> {
+    let asset = match provider.asset(version, &format!("{}.minisig", name)) {
+        Ok(asset) => asset,
+        Err(_) => return Ok(None),
+    };
+
+    let file = match asset.download(Arc::new(Progress::default())) {
+        DownloadResult::Complete(file) => file,
+        DownloadResult::Cancelled => return Err("Signature download cancelled".into()),
+        DownloadResult::Error(e) => {
+            return Err(format!("Failed to download {}: {}", asset.name(), e).into())
+        }
+    };
+
+    let mut content = String::new();
+    file.take(MAX_SIGNATURE_SIZE as u64 + 1)
+        .read_to_string(&mut content)?;
+    if content.len() > MAX_SIGNATURE_SIZE {
+        return Err(SignatureError::Invalid("signature file is too big".into()).into());
+    }
+
+    match Signature::decode(&content) {
+        Ok(signature) => Ok(Some(signature)),
+        Err(e) => Err(SignatureError::Invalid(e.to_string()).into()),
+    }
+}
+
+/// Verifies the content of the reader with the signature
+fn verify<R: Read>(
+    mut reader: R,
+    signature: &Signature,
+    public_key: &PublicKey,
+) -> Result<(), Box<dyn Error>> {
+    let invalid = |e: minisign_verify::Error| SignatureError::Invalid(e.to_string());
+
+    let mut verifier = match public_key.0.verify_stream(signature) {
+        Ok(verifier) => verifier,
+        // Legacy signatures sign the content itself instead of its hash
+        Err(minisign_verify::Error::UnsupportedLegacyMode) => {
+            let mut content = Vec::new();
+            reader.read_to_end(&mut content)?;
+            public_key
+                .0
+                .verify(&content, signature, true)
+                .map_err(invalid)?;
+            return Ok(());
+        }
+        Err(e) => return Err(invalid(e).into()),
+    };
+
+    let mut buf = [0; 16384];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(len) => verifier.update(&buf[..len]),
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    verifier.finalize().map_err(invalid)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::provider::GiteaProvider;
+    use crate::test_server::{Response, TestServer};
+    use std::io::Write;
+
+    // Test vectors of minisign (signature of "test")
+    const PUBLIC_KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+    const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1556193335\tfile:test
+y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==
+";
+    const LEGACY_SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RWQf6LRCGA9i59SLOFxz6NxvASXDJeRtuZykwQepbDEGt87ig1BNpWaVWuNrm73YiIiJbq71Wi+dP9eKL8OC351vwIasSSbXxwA=
+trusted comment: timestamp:1555779966\tfile:test
+QtKMXWyYcwdpZAlPF7tE2ENJkRd1ujvKjlj1m9RtHTBnZPa5WKU5uWRs5GoP5M/VqE81QFuMKI5k/SfNQUaOAA==
+";
+
+    fn key() -> PublicKey {
+        PUBLIC_KEY.parse().unwrap()
+    }
+
+    fn signature(content: &str) -> Signature {
+        Signature::decode(content).unwrap()
+    }
+
+    #[test]
+    fn parse_key() {
+        let file = format!("untrusted comment: minisign public key\n{}\n", PUBLIC_KEY);
+        let key = file.parse::<PublicKey>().unwrap();
+        assert!(verify(&b"test"[..], &signature(SIGNATURE), &key).is_ok());
+        assert!("RWQinvalid".parse::<PublicKey>().is_err());
+    }
+
+    #[test]
+    fn verify_content() {
+        assert!(verify(&b"test"[..], &signature(SIGNATURE), &key()).is_ok());
+        assert!(verify(&b"test"[..], &signature(LEGACY_SIGNATURE), &key()).is_ok());
+
+        let error = verify(&b"Test"[..], &signature(SIGNATURE), &key()).unwrap_err();
+        assert!(error.is::<SignatureError>());
+
+        // Same key id, different key
+        let other: PublicKey = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO4"
+            .parse()
+            .unwrap();
+        assert!(verify(&b"test"[..], &signature(SIGNATURE), &other).is_err());
+    }
+
+    /// Release with the asset `name` and, if `signed`, the test signature (made for "test")
+    fn server(name: &'static str, signed: bool) -> TestServer {
+        TestServer::new(move |request| {
+            let base = format!("http://{}", request.header("host").unwrap_or_default());
+            let signature = match signed {
+                true => format!(
+                    r#", {{ "name": "{0}.minisig", "size": 1, "browser_download_url": "{1}/{0}.minisig" }}"#,
+                    name, base
+                ),
+                false => String::new(),
+            };
+            match request.path.as_str() {
+                "/api/v1/repos/org/app/releases" => Response::json(format!(
+                    r#"[{{ "tag_name": "v1.0.0", "name": "1.0.0", "assets": [
+                        {{ "name": "{0}", "size": 4, "browser_download_url": "{1}/{0}" }}{2}
+                    ] }}]"#,
+                    name, base, signature
+                )),
+                path if path == format!("/{}.minisig", name) => Response::ok(SIGNATURE),
+                _ => Response::not_found(),
+            }
+        })
+    }
+
+    fn downloaded(content: &str) -> File {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(content.as_bytes()).unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file
+    }
+
+    #[test]
+    fn verify_release_asset() {
+        let server = server("test", true);
+        let mut provider = GiteaProvider::new(&server.base(), "org/app");
+        provider.fetch().unwrap();
+        let version = Version::new(1, 0, 0);
+
+        let mut file = downloaded("test");
+        verify_asset(&provider, &version, "test", &mut file, &key(), false).unwrap();
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "test");
+
+        // Badly signed assets are rejected even if unsigned ones are allowed
+        let mut file = downloaded("tampered");
+        let error = verify_asset(&provider, &version, "test", &mut file, &key(), true).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<SignatureError>(),
+            Some(SignatureError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn verify_stream() {
+        let server = server("test", true);
+        let mut provider = GiteaProvider::new(&server.base(), "org/app");
+        provider.fetch().unwrap();
+        let version = Version::new(1, 0, 0);
+
+        let downloaded = AssetSignature::download(&provider, &version, "test", false)
+            .unwrap()
+            .unwrap();
+        let key = key();
+        let mut verifier = downloaded.verifier(&key).unwrap();
+        verifier.update(b"te");
+        verifier.update(b"st");
+        assert!(verifier.finalize().is_ok());
+
+        let mut verifier = downloaded.verifier(&key).unwrap();
+        verifier.update(b"tampered");
+        assert!(verifier.finalize().is_err());
+
+        // Legacy signatures can only be verified as a whole
+        let legacy = AssetSignature {
+            name: "app.zip".into(),
+            signature: signature(LEGACY_SIGNATURE),
+        };
+        assert!(legacy.verifier(&key).is_err());
+    }
+
+    #[test]
+    fn signature_of_other_asset() {
+        let server = server("app.zip", true);
+        let mut provider = GiteaProvider::new(&server.base(), "org/app");
+        provider.fetch().unwrap();
+        let version = Version::new(1, 0, 0);
+
+        // Valid signature, but made for "test"
+        let mut file = downloaded("test");
+        let error =
+            verify_asset(&provider, &version, "app.zip", &mut file, &key(), true).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<SignatureError>(),
+            Some(SignatureError::Invalid(_))
+        ));
+
+        let downloaded = AssetSignature::download(&provider, &version, "app.zip", false)
+            .unwrap()
+            .unwrap();
+        let key = key();
+        let mut verifier = downloaded.verifier(&key).unwrap();
+        verifier.update(b"test");
+        assert!(verifier.finalize().is_err());
+    }
+
+    #[test]
+    fn unsigned_release_asset() {
+        let server = server("app.zip", false);
+        let mut provider = GiteaProvider::new(&server.base(), "org/app");
+        provider.fetch().unwrap();
+        let version = Version::new(1, 0, 0);
+
+        let mut file = downloaded("test");
+        let error =
+            verify_asset(&provider, &version, "app.zip", &mut file, &key(), false).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<SignatureError>(),
+            Some(&SignatureError::Missing("app.zip".into()))
+        );
+
+        assert!(verify_asset(&provider, &version, "app.zip", &mut file, &key(), true).is_ok());
+    }
+}
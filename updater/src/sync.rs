@@ -0,0 +1,290 @@
+//! File-level incremental installs.
+//!
+//! A release can publish the list of the files in an asset with their hashes
+//! (as "<asset name>.files.json"):
+//! ```json
+//! {
+//!   "files": [
+//!     { "path": "bin/app", "size": 1048576, "sha256": "<hex encoded hash>" }
+//!   ]
+//! }
+//! ```
+//!
+//! Files which didn't change since the installed version are hard linked (or copied if
+//! linking isn't possible) from its directory, so only the changed files have to be extracted.
+//! Entries the list doesn't cover (directories, links, unlisted files) are always extracted.
+
+use crate::provider::to_hex;
+use crate::Progress;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs::File;
+use std::io::{ErrorKind, Read};
+use std::path::{Component, Path};
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FileList {
+    pub files: Vec<FileEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileEntry {
+    /// Path relative to the install directory, separated by '/'
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl FileList {
+    /// Creates the list of the files in the directory
+    pub fn create(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let mut paths = Vec::new();
+        list_files(dir, "", &mut paths)?;
+
+        let mut files = Vec::with_capacity(paths.len());
+        for path in paths {
+            let full_path = dir.join(&path);
+            files.push(FileEntry {
+                size: full_path.metadata()?.len(),
+                sha256: hash_file(&full_path)?,
+                path,
+            });
+        }
+
+        Ok(Self { files })
+    }
+
+    /// Parses the published list
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self, Box<dyn Error>> {
+        let list: Self = json::from_reader(reader)?;
+        for file in list.files.iter() {
+            if normalize(Path::new(&file.path)).as_deref() != Some(file.path.as_str()) {
+                return Err(format!("Disallowed path in file list: {}", file.path).into());
+            }
+        }
+        Ok(list)
+    }
+
+    /// Hard links (or copies) the unchanged files of the old directory into the target.
+    /// Returns the paths of the files which still have to be installed.
+    pub fn link_unchanged(
+        &self,
+        old: &Path,
+        target: &Path,
+        progress: &Progress,
+    ) -> Result<HashSet<String>, Box<dyn Error>> {
+        progress.set_maximum(self.files.iter().map(|f| f.size).sum());
+        progress.set_indeterminate(false);
+
+        let mut missing = HashSet::new();
+        for file in self.files.iter() {
+            if progress.cancelled() {
+                break;
+            }
+
+            let old_path = old.join(&file.path);
+            let unchanged = match old_path.symlink_metadata() {
+                Ok(metadata) if metadata.is_file() && metadata.len() == file.size => {
+                    hash_file(&old_path)?.eq_ignore_ascii_case(&file.sha256)
+                }
+                _ => false,
+            };
+            if !unchanged {
+                missing.insert(file.path.clone());
+                continue;
+            }
+
+            let path = target.join(&file.path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            if std::fs::hard_link(&old_path, &path).is_err() {
+                std::fs::copy(&old_path, &path)?;
+            }
+            progress.add_current(file.size);
+        }
+
+        Ok(missing)
+    }
+
+    /// Gets the paths of the files which were linked from the old directory,
+    /// ie. the listed ones which aren't missing.
+    /// Anything else in the archive (changed or unlisted files, directories, links)
+    /// still has to be extracted.
+    pub fn linked<'a>(&'a self, missing: &HashSet<String>) -> HashSet<&'a str> {
+        self.files
+            .iter()
+            .map(|file| file.path.as_str())
+            .filter(|path| !missing.contains(*path))
+            .collect()
+    }
+
+    /// Checks the listed files in the target directory against their hashes
+    pub fn verify<'a, I: IntoIterator<Item = &'a String>>(
+        &self,
+        target: &Path,
+        paths: I,
+    ) -> Result<(), Box<dyn Error>> {
+        let paths: HashSet<&String> = paths.into_iter().collect();
+        for file in self.files.iter().filter(|f| paths.contains(&f.path)) {
+            let actual = match hash_file(&target.join(&file.path)) {
+                Ok(hash) => hash,
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    return Err(format!("{} is missing from the asset", file.path).into())
+                }
+                Err(e) => return Err(e.into()),
+            };
+            if !actual.eq_ignore_ascii_case(&file.sha256) {
+                return Err(format!("SHA-256 mismatch of {}", file.path).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Converts the relative path into the '/' separated form used in the file lists.
+/// None if the path isn't relative or leaves its directory.
+pub(crate) fn normalize(path: &Path) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    match parts.is_empty() {
+        true => None,
+        false => Some(parts.join("/")),
+    }
+}
+
+/// Collects the '/' separated relative paths of the files in the directory recursively
+pub(crate) fn list_files(
+    dir: &Path,
+    prefix: &str,
+    paths: &mut Vec<String>,
+) -> Result<(), Box<dyn Error>> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let name = entry.file_name();
+        let name = name.to_str().ok_or("Non UTF-8 file name")?;
+        let path = format!("{}{}", prefix, name);
+
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            list_files(&entry.path(), &format!("{}/", path), paths)?;
+        } else if file_type.is_file() {
+            paths.push(path);
+        } else {
+            return Err(format!("Unsupported file type: {}", path).into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Gets the hex encoded SHA-256 hash of the file
+pub(crate) fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn directory(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(name);
+        if dir.exists() {
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+        for (path, content) in [
+            ("1.0.0/app.bin", "old binary"),
+            ("1.0.0/data/static.txt", "static"),
+            ("1.1.0/app.bin", "new binary"),
+            ("1.1.0/data/static.txt", "static"),
+            ("1.1.0/data/added.txt", "added"),
+        ] {
+            let path = dir.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn link_unchanged_files() {
+        let dir = directory("updater_sync_test_link");
+        let list = FileList::create(&dir.join("1.1.0")).unwrap();
+        assert_eq!(list.files.len(), 3);
+        assert_eq!(list.files[1].path, "data/added.txt");
+
+        let target = dir.join("target");
+        let progress = Progress::default();
+        let missing = list
+            .link_unchanged(&dir.join("1.0.0"), &target, &progress)
+            .unwrap();
+        assert_eq!(list.linked(&missing), HashSet::from(["data/static.txt"]));
+
+        let mut missing: Vec<&String> = missing.iter().collect();
+        missing.sort();
+        assert_eq!(missing, ["app.bin", "data/added.txt"]);
+        assert_eq!(
+            std::fs::read_to_string(target.join("data/static.txt")).unwrap(),
+            "static"
+        );
+        assert_eq!(progress.current(), 6);
+
+        // Only the linked file is there
+        assert!(list
+            .verify(&target, ["data/static.txt".to_string()].iter())
+            .is_ok());
+        assert!(list.verify(&target, missing).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn verify_files() {
+        let dir = directory("updater_sync_test_verify");
+        let list = FileList::create(&dir.join("1.1.0")).unwrap();
+        let paths: Vec<String> = list.files.iter().map(|f| f.path.clone()).collect();
+
+        assert!(list.verify(&dir.join("1.1.0"), &paths).is_ok());
+        std::fs::write(dir.join("1.1.0/app.bin"), "tampered").unwrap();
+        assert!(list.verify(&dir.join("1.1.0"), &paths).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn parse_list() {
+        let list = FileList::from_reader(
+            &br#"{ "files": [{ "path": "bin/app", "size": 1, "sha256": "00" }] }"#[..],
+        )
+        .unwrap();
+        assert_eq!(list.files[0].path, "bin/app");
+
+        for path in ["../evil", "/etc/passwd", "./bin/app", ""] {
+            let content = format!(
+                r#"{{ "files": [{{ "path": "{}", "size": 1, "sha256": "00" }}] }}"#,
+                path
+            );
+            assert!(FileList::from_reader(content.as_bytes()).is_err());
+        }
+    }
+
+    #[test]
+    fn normalize_paths() {
+        assert_eq!(normalize(Path::new("./bin/app")).unwrap(), "bin/app");
+        assert_eq!(normalize(Path::new("bin/../app")), None);
+        assert_eq!(normalize(Path::new("/bin/app")), None);
+    }
+}
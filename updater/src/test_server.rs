@@ -0,0 +1,210 @@
+//! Minimal HTTP/1.1 server used as a stand-in for remote services in tests.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+type Handler = Box<dyn Fn(&Request) -> Response + Send + Sync>;
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: HashMap<String, String>,
+}
+
+impl Request {
+    /// Gets a header value by its (case-insensitive) name
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(|v| v.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+    /// Number of body bytes actually sent before closing the connection
+    pub sent: Option<usize>,
+}
+
+impl Response {
+    pub fn new<B: Into<Vec<u8>>>(status: u16, body: B) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+            sent: None,
+        }
+    }
+
+    pub fn ok<B: Into<Vec<u8>>>(body: B) -> Self {
+        Self::new(200, body)
+    }
+
+    pub fn json<B: Into<Vec<u8>>>(body: B) -> Self {
+        Self::ok(body).with_header("Content-Type", "application/json")
+    }
+
+    pub fn not_found() -> Self {
+        Self::new(404, "Not Found")
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Closes the connection after sending `len` bytes of the body (simulates a dropped connection)
+    pub fn interrupted(mut self, len: usize) -> Self {
+        self.sent = Some(len);
+        self
+    }
+}
+
+pub struct TestServer {
+    port: u16,
+    running: Arc<AtomicBool>,
+    requests: Arc<Mutex<Vec<Request>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl TestServer {
+    /// Starts the server on a random local port. Every request is answered by `handler`.
+    pub fn new<F>(handler: F) -> Self
+    where
+        F: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind test server");
+        let port = listener.local_addr().unwrap().port();
+        let running = Arc::new(AtomicBool::new(true));
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let handler: Handler = Box::new(handler);
+        let thread = {
+            let running = running.clone();
+            let requests = requests.clone();
+            thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if !running.load(Ordering::Acquire) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        serve(stream, &handler, &requests);
+                    }
+                }
+            })
+        };
+
+        Self {
+            port,
+            running,
+            requests,
+            thread: Some(thread),
+        }
+    }
+
+    /// Starts a server that answers with the route matching the request path (without query)
+    pub fn with_routes(routes: Vec<(&str, Response)>) -> Self {
+        let routes: HashMap<String, Response> = routes
+            .into_iter()
+            .map(|(path, response)| (path.to_string(), response))
+            .collect();
+
+        Self::new(move |request| {
+            let path = request.path.split('?').next().unwrap_or_default();
+            routes
+                .get(path)
+                .cloned()
+                .unwrap_or_else(Response::not_found)
+        })
+    }
+
+    /// Base url of the server (without trailing slash)
+    pub fn base(&self) -> String {
+        format!("http://127.0.0.1:{}", self.port)
+    }
+
+    /// Absolute url of the specified path
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base(), path)
+    }
+
+    /// The requests received so far
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::Release);
+        // Wake up the listener so the thread can exit
+        let _ = TcpStream::connect(("127.0.0.1", self.port));
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn serve(stream: TcpStream, handler: &Handler, requests: &Mutex<Vec<Request>>) {
+    let mut reader = BufReader::new(match stream.try_clone() {
+        Ok(s) => s,
+        Err(_) => return,
+    });
+
+    let mut line = String::new();
+    if reader.read_line(&mut line).is_err() {
+        return;
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let request = Request {
+        method,
+        path,
+        headers,
+    };
+    let response = handler(&request);
+    requests.lock().unwrap().push(request);
+
+    let mut stream = stream;
+    let mut head = format!(
+        "HTTP/1.1 {} Test\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    let _ = stream.write_all(head.as_bytes());
+    let sent = response.sent.unwrap_or(response.body.len());
+    let _ = stream.write_all(&response.body[..sent]);
+    let _ = stream.flush();
+}
@@ -3,10 +3,16 @@ use regex::Regex;
 use semver::Version;
 use std::error::Error;
 
-/// Extracts only the semver from a string
+/// Extracts only the semver from a string.
+///
+/// Prerelease tags are kept if they are one of the common ones
+/// (eg. "1.2.0-beta.1", "1.2.0-rc2", "1.2.0-nightly.20240101").
 pub fn extract(version: &str) -> Result<Version, Box<dyn Error>> {
     lazy_static! {
-        static ref RE: Regex = Regex::new(r"\d+\u{2E}\d+\u{2E}\d+").unwrap();
+        static ref RE: Regex = Regex::new(
+            r"\d+\u{2E}\d+\u{2E}\d+(-(alpha|beta|rc|pre|preview|dev|nightly|snapshot)\d*(\u{2E}\d+)*\b)?"
+        )
+        .unwrap();
     }
     let mat = RE.find(version).ok_or("Version regex match failed")?;
     Ok(Version::parse(version[mat.start()..mat.end()].into())?)
@@ -82,6 +88,24 @@ mod tests {
         assert_eq!(Version::new(1, 2, 3), sver);
     }
 
+    #[test]
+    fn extract_prerelease() {
+        let sver = extract("v1.2.3-beta.2").unwrap();
+        assert_eq!(Version::parse("1.2.3-beta.2").unwrap(), sver);
+
+        let sver = extract("app-1.2.3-rc1.tar.gz").unwrap();
+        assert_eq!(Version::parse("1.2.3-rc1").unwrap(), sver);
+    }
+
+    #[test]
+    fn extract_ignores_suffix() {
+        let sver = extract("app-1.2.3-win-x64.zip").unwrap();
+        assert_eq!(Version::new(1, 2, 3), sver);
+
+        let sver = extract("app-1.2.3-prebuilt.zip").unwrap();
+        assert_eq!(Version::new(1, 2, 3), sver);
+    }
+
     #[test]
     fn extract_incorrect() {
         let ver = "1.W.3";